@@ -86,6 +86,13 @@ class PluginPeer(RpcPeer):
         return self.send_rpc_sync('get_line', {'line': i})
     def set_line_fg_spans(self, i, spans):
         self.send_rpc('set_line_fg_spans', {'line': i, 'spans': spans})
+    def edit(self, start, end, text, rev = None, priority = None):
+        params = {'start': start, 'end': end, 'text': text}
+        if rev is not None:
+            params['rev'] = rev
+        if priority is not None:
+            params['priority'] = priority
+        return self.send_rpc_sync('edit', params)
 
 def handler(method, params, peer):
     if method == 'ping':
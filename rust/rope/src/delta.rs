@@ -261,12 +261,91 @@ impl<N: NodeInfo> Delta<N> {
         );
         (Interval::new_closed_open(iv_start, iv_end), new_len)
     }
+
+    /// The separate edits the delta makes, in order: each replaces an interval
+    /// of the base sequence with an inserted one, which may be empty.
+    pub fn edits(&self) -> Vec<(Interval, Node<N>)> {
+        let mut edits = Vec::new();
+        let mut base_pos = 0;
+        let mut inserted: Option<Node<N>> = None;
+        for el in &self.els {
+            match *el {
+                DeltaElement::Copy(beg, end) => {
+                    if beg > base_pos || inserted.is_some() {
+                        edits.push((Interval::new_closed_open(base_pos, beg),
+                            inserted.take().unwrap_or_default()));
+                    }
+                    base_pos = end;
+                }
+                DeltaElement::Insert(ref n) => {
+                    inserted = Some(match inserted.take() {
+                        Some(prev) => Node::concat(prev, n.clone()),
+                        None => n.clone(),
+                    });
+                }
+            }
+        }
+        if base_pos < self.base_len || inserted.is_some() {
+            edits.push((Interval::new_closed_open(base_pos, self.base_len),
+                inserted.unwrap_or_default()));
+        }
+        edits
+    }
+}
+
+/// A builder for deltas made of several edits, each replacing an interval of
+/// the base sequence. Intervals must be non-overlapping and added in order.
+pub struct DeltaBuilder<N: NodeInfo> {
+    delta: Delta<N>,
+    last_offset: usize,
+    is_empty: bool,
+}
+
+impl<N: NodeInfo> DeltaBuilder<N> {
+    pub fn new(base_len: usize) -> DeltaBuilder<N> {
+        DeltaBuilder {
+            delta: Delta { els: Vec::new(), base_len: base_len },
+            last_offset: 0,
+            is_empty: true,
+        }
+    }
+
+    /// Replace the contents of `interval` with `rope`. Panics if the interval
+    /// starts before the end of the previous one.
+    pub fn replace(&mut self, interval: Interval, rope: Node<N>) {
+        let (start, end) = interval.start_end();
+        assert!(start >= self.last_offset, "delta edits must be sorted and non-overlapping");
+        if start > self.last_offset {
+            self.delta.els.push(DeltaElement::Copy(self.last_offset, start));
+        }
+        if rope.len() > 0 {
+            self.delta.els.push(DeltaElement::Insert(rope));
+        }
+        self.last_offset = end;
+        self.is_empty = false;
+    }
+
+    pub fn delete(&mut self, interval: Interval) {
+        self.replace(interval, Node::default());
+    }
+
+    /// Determine whether no edits have been added.
+    pub fn is_empty(&self) -> bool {
+        self.is_empty
+    }
+
+    pub fn build(mut self) -> Delta<N> {
+        if self.last_offset < self.delta.base_len {
+            self.delta.els.push(DeltaElement::Copy(self.last_offset, self.delta.base_len));
+        }
+        self.delta
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use rope::{Rope, RopeInfo};
-    use delta::{Delta};
+    use delta::{Delta, DeltaBuilder};
     use interval::Interval;
     use subset::{Subset, SubsetBuilder};
 
@@ -298,6 +377,49 @@ mod tests {
         assert_eq!("herald", d.apply_to_string("hello world"));
     }
 
+    #[test]
+    fn builder() {
+        let mut b = DeltaBuilder::new(11);
+        b.replace(Interval::new_closed_open(0, 1), Rope::from("j"));
+        b.delete(Interval::new_closed_open(4, 6));
+        b.replace(Interval::new_closed_open(11, 11), Rope::from("!"));
+        let d = b.build();
+        assert_eq!("jellworld!", d.apply_to_string("hello world"));
+        let (iv, new_len) = d.summary();
+        assert_eq!((0, 11), iv.start_end());
+        assert_eq!(10, new_len);
+    }
+
+    #[test]
+    fn edits() {
+        let mut b = DeltaBuilder::new(11);
+        b.replace(Interval::new_closed_open(0, 1), Rope::from("j"));
+        b.delete(Interval::new_closed_open(4, 6));
+        b.replace(Interval::new_closed_open(11, 11), Rope::from("!"));
+        let edits: Vec<_> = b.build().edits().into_iter()
+            .map(|(iv, rope)| (iv.start_end(), String::from(rope)))
+            .collect();
+        assert_eq!(vec![((0, 1), "j".to_string()), ((4, 6), "".to_string()),
+            ((11, 11), "!".to_string())], edits);
+    }
+
+    #[test]
+    fn builder_empty() {
+        let b = DeltaBuilder::<RopeInfo>::new(11);
+        assert!(b.is_empty());
+        assert_eq!("hello world", b.build().apply_to_string("hello world"));
+    }
+
+    #[test]
+    fn builder_factor() {
+        let mut b = DeltaBuilder::new(11);
+        b.replace(Interval::new_closed_open(0, 0), Rope::from("/*"));
+        b.replace(Interval::new_closed_open(5, 5), Rope::from("*/"));
+        let (d1, ss) = b.build().factor();
+        assert_eq!("/*hello*/ world", d1.apply_to_string("hello world"));
+        assert!(ss.is_trivial());
+    }
+
     #[test]
     fn factor() {
         let d = Delta::simple_edit(Interval::new_closed_open(1, 9), Rope::from("era"), 11);
@@ -102,11 +102,21 @@ impl Engine {
         self.revs.last().unwrap().rev_id
     }
 
+    /// The number of revisions still in the history.
+    pub fn n_revs(&self) -> usize {
+        self.revs.len()
+    }
+
     /// Get text of head revision.
     pub fn get_head(&self) -> Rope {
         self.get_rev(self.revs.len() - 1)
     }
 
+    /// Get text of the revision with id `rev_id`, if it is still in the history.
+    pub fn get_rev_text(&self, rev_id: usize) -> Option<Rope> {
+        self.find_rev(rev_id).map(|ix| self.get_rev(ix))
+    }
+
     /// A delta that, when applied to previous head, results in the current head. Panics
     /// if there is not at least one edit.
     pub fn delta_rev_head(&self, base_rev: usize) -> Delta<RopeInfo> {
@@ -30,16 +30,57 @@ extern crate crossbeam;
 mod macros;
 
 use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
 use std::io;
-use std::io::{BufRead, Write};
+use std::io::{BufRead, Read, Write};
 use std::sync::{Arc, Mutex, Condvar};
 use std::sync::mpsc;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use crossbeam::scope;
 
 use serde_json::builder::ObjectBuilder;
 use serde_json::Value;
 
+static STRUCTURED_LOG: AtomicBool = AtomicBool::new(false);
+
+/// Selects whether diagnostics written by `print_err!` are human-readable text
+/// (the default), or one JSON object per line with `level` and `message`
+/// members, for front-ends that parse stderr.
+pub fn set_structured_log(structured: bool) {
+    STRUCTURED_LOG.store(structured, Ordering::Relaxed);
+}
+
+/// Writes a diagnostic line to stderr; the implementation of `print_err!`.
+#[doc(hidden)]
+pub fn write_log(level: &str, args: fmt::Arguments) {
+    let message = fmt::format(args);
+    let line = if STRUCTURED_LOG.load(Ordering::Relaxed) {
+        serde_json::to_string(&ObjectBuilder::new()
+            .insert("level", level)
+            .insert("message", &message)
+            .unwrap()).unwrap()
+    } else {
+        message.clone()
+    };
+    if let Err(e) = writeln!(&mut io::stderr(), "{}", line) {
+        panic!("Failed to write to stderr.\
+            \nOriginal error output: {}\
+            \nSecondary error writing to stderr: {}", message, e);
+    }
+}
+
+/// How messages are delimited on the channel, in both directions.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Framing {
+    /// Each message is one line of JSON, ended by a newline.
+    Newline,
+    /// Each message is preceded by headers, as in the Language Server
+    /// Protocol: a `Content-Length` giving the length of the JSON in bytes,
+    /// possibly others (which are ignored), and an empty line, each line
+    /// ended by `\r\n`.
+    ContentLength,
+}
+
 #[derive(Debug)]
 pub enum Error {
     /// An IO error occurred on the underlying communication channel.
@@ -65,6 +106,7 @@ struct RpcState<W: Write> {
     rx_queue: Mutex<VecDeque<Value>>,
     rx_cvar: Condvar,
     writer: Mutex<W>,
+    framing: Framing,
     id: AtomicUsize,
     pending: Mutex<BTreeMap<usize, mpsc::Sender<Result<Value, Error>>>>,
 }
@@ -90,10 +132,16 @@ impl<W:Write + Send> RpcLoop<W> {
     /// Creates a new `RpcLoop` with the given output stream (which is used for
     /// sending requests and notifications, as well as responses).
     pub fn new(writer: W) -> Self {
+        RpcLoop::with_framing(writer, Framing::Newline)
+    }
+
+    /// Like `new`, but with messages in both directions delimited by `framing`.
+    pub fn with_framing(writer: W, framing: Framing) -> Self {
         let rpc_peer = RpcPeer(Arc::new(RpcState {
             rx_queue: Mutex::new(VecDeque::new()),
             rx_cvar: Condvar::new(),
             writer: Mutex::new(writer),
+            framing: framing,
             id: AtomicUsize::new(0),
             pending: Mutex::new(BTreeMap::new()),
         }));
@@ -111,6 +159,24 @@ impl<W:Write + Send> RpcLoop<W> {
     // Reads raw json from the input stream.
     fn read_json<R: BufRead>(&mut self, reader: &mut R)
             -> Option<serde_json::error::Result<Value>> {
+        if self.peer.0.framing == Framing::ContentLength {
+            return self.read_content_length(reader).and_then(|len| {
+                // the buffer grows as the body arrives, so a bogus length
+                // can't exhaust memory up front
+                let mut body = Vec::new();
+                match reader.by_ref().take(len as u64).read_to_end(&mut body) {
+                    Ok(n) if n == len => Some(serde_json::from_slice::<Value>(&body)),
+                    Ok(n) => {
+                        print_err!("input ended after {} of a message's {} bytes", n, len);
+                        None
+                    }
+                    Err(e) => {
+                        print_err!("error {} reading a message of {} bytes", e, len);
+                        None
+                    }
+                }
+            });
+        }
         self.buf.clear();
         if reader.read_line(&mut self.buf).is_ok() {
             if self.buf.is_empty() {
@@ -121,6 +187,45 @@ impl<W:Write + Send> RpcLoop<W> {
         None
     }
 
+    // Reads the headers of a message, returning its length. As the start of
+    // the next message can't be found after bad headers, they end the input.
+    fn read_content_length<R: BufRead>(&mut self, reader: &mut R) -> Option<usize> {
+        let mut len = None;
+        loop {
+            self.buf.clear();
+            match reader.read_line(&mut self.buf) {
+                Ok(0) => {
+                    if len.is_some() {
+                        print_err!("input ended in the headers of a message");
+                    }
+                    return None;
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    print_err!("error {} reading message headers", e);
+                    return None;
+                }
+            }
+            let line = self.buf.trim_end_matches(&['\r', '\n'][..]);
+            if line.is_empty() {
+                break;
+            }
+            let mut parts = line.splitn(2, ':');
+            let name = parts.next().unwrap().trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                len = parts.next().and_then(|value| value.trim().parse::<usize>().ok());
+                if len.is_none() {
+                    print_err!("invalid header: {}", line);
+                    return None;
+                }
+            }
+        }
+        if len.is_none() {
+            print_err!("message without a Content-Length header");
+        }
+        len
+    }
+
     /// Starts a main loop. The reader is supplied via a closure, as basically
     /// a workaround so that the reader doesn't have to be `Send`. Internally, the
     /// main loop starts a separate thread for I/O, and at startup that thread calls
@@ -133,12 +238,28 @@ impl<W:Write + Send> RpcLoop<W> {
     /// on the channel. At the moment, there is no way for there to be more than one
     /// incoming request to be outstanding.
     ///
+    /// The result the handler returns is sent as the response to a request, and
+    /// dropped for a notification, which has no id to respond to.
+    ///
     /// This method returns when the input channel is closed.
     pub fn mainloop<R: BufRead,
         RF: Send + FnOnce() -> R,
         F: FnMut(&str, &Value) -> Option<Value>>(&mut self,
             rf: RF,
-            mut f: F) {
+            f: F) {
+        self.mainloop_with_idle(rf, f, || ())
+    }
+
+    /// Like `mainloop`, but also calls `idle` after handling a request (and
+    /// sending any response) when no other request is waiting, so that
+    /// deferred work can be done without delaying responses.
+    pub fn mainloop_with_idle<R: BufRead,
+        RF: Send + FnOnce() -> R,
+        F: FnMut(&str, &Value) -> Option<Value>,
+        I: FnMut()>(&mut self,
+            rf: RF,
+            mut f: F,
+            mut idle: I) {
         crossbeam::scope(|scope| {
             let peer = self.get_peer();
             scope.spawn(move|| {
@@ -167,14 +288,18 @@ impl<W:Write + Send> RpcLoop<W> {
                 print_err!("to core: {:?}", json);
                 match parse_rpc_request(&json) {
                     Some((id, method, params)) => {
-                        if let Some(result) = f(method, params) {
-                            peer.respond(&result, id);
-                        } else if let Some(id) = id {
-                            print_err!("RPC with id={:?} not responded", id);
+                        match (f(method, params), id) {
+                            (Some(result), Some(_)) => peer.respond(&result, id),
+                            (None, Some(id)) =>
+                                print_err!("RPC with id={:?} not responded", id),
+                            (_, None) => ()
                         }
                     }
                     None => print_err!("invalid RPC request")
                 }
+                if !peer.request_is_pending() {
+                    idle();
+                }
             }
         });
     }
@@ -183,7 +308,10 @@ impl<W:Write + Send> RpcLoop<W> {
 impl<W:Write> RpcPeer<W> {
     fn send(&self, v: &Value) -> Result<(), io::Error> {
         let mut s = serde_json::to_string(v).unwrap();
-        s.push('\n');
+        match self.0.framing {
+            Framing::Newline => s.push('\n'),
+            Framing::ContentLength => s = format!("Content-Length: {}\r\n\r\n{}", s.len(), s),
+        }
         //print_err!("from core: {}", s);
         self.0.writer.lock().unwrap().write_all(s.as_bytes())
         // Technically, maybe we should flush here, but doesn't seem to be reqiured.
@@ -273,7 +401,8 @@ impl<W:Write> RpcPeer<W> {
     /// is intended to reduce latency for bulk operations done in the background;
     /// the handler can do this work, periodically check
     ///
-    /// TODO: some way to schedule an "idle task" when no requests are pending.
+    /// Work that can wait until no requests are pending can instead be done
+    /// in the idle callback of `mainloop_with_idle`.
     pub fn request_is_pending(&self) -> bool {
         let queue = self.0.rx_queue.lock().unwrap();
         !queue.is_empty()
@@ -290,3 +419,46 @@ impl<W:Write> Clone for RpcPeer<W> {
 fn dict_get_string<'a>(dict: &'a BTreeMap<String, Value>, key: &str) -> Option<&'a str> {
     dict.get(key).and_then(Value::as_string)
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use {Framing, RpcLoop};
+
+    fn content_length(input: &str) -> Option<usize> {
+        let mut rpc_loop = RpcLoop::with_framing(Vec::new(), Framing::ContentLength);
+        rpc_loop.read_content_length(&mut input.as_bytes())
+    }
+
+    #[test]
+    fn content_length_headers() {
+        assert_eq!(Some(2), content_length("Content-Length: 2\r\n\r\n{}"));
+        assert_eq!(Some(2), content_length("content-length:2\nX-Other: a:b\r\n\r\n{}"));
+        assert_eq!(None, content_length("X-Other: 2\r\n\r\n{}"));
+        assert_eq!(None, content_length("Content-Length: two\r\n\r\n{}"));
+        assert_eq!(None, content_length("Content-Length: -1\r\n\r\n{}"));
+        assert_eq!(None, content_length("Content-Length: 2\r\n"));
+        assert_eq!(None, content_length(""));
+    }
+
+    #[test]
+    fn content_length_bodies() {
+        let mut rpc_loop = RpcLoop::with_framing(Vec::new(), Framing::ContentLength);
+        let mut input = "Content-Length: 7\r\n\r\n{\"a\":1}Content-Length: 99\r\n\r\n{}".as_bytes();
+        let first = rpc_loop.read_json(&mut input).unwrap().unwrap();
+        assert_eq!(Some(1), first.find("a").and_then(|a| a.as_u64()));
+        assert!(rpc_loop.read_json(&mut input).is_none());
+        let mut huge = "Content-Length: 18446744073709551615\r\n\r\n{}".as_bytes();
+        assert!(rpc_loop.read_json(&mut huge).is_none());
+    }
+
+    #[test]
+    fn notification_results_dropped() {
+        let mut rpc_loop = RpcLoop::new(Vec::new());
+        let input = "{\"method\":\"a\",\"params\":[]}\n\
+                     {\"id\":2,\"method\":\"b\",\"params\":[]}\n".as_bytes();
+        rpc_loop.mainloop(|| input, |method, _| Some(Value::String(method.to_string())));
+        let sent = rpc_loop.peer.0.writer.lock().unwrap().clone();
+        assert_eq!("{\"id\":2,\"result\":\"b\"}\n", String::from_utf8(sent).unwrap());
+    }
+}
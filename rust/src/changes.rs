@@ -0,0 +1,134 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The regions of the text edited during the session, for jumping between
+//! them and for trimming edited lines on save.
+
+use std::cmp::{min, max};
+
+use xi_rope::interval::Interval;
+
+const MAX_CHANGES: usize = 100;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Region {
+    start: usize,
+    end: usize,
+    // when the region was last edited; the oldest are dropped first
+    seq: usize,
+}
+
+/// Edited regions, kept sorted, non-overlapping, and in the coordinates of the
+/// current text. Touching regions are merged.
+#[derive(Default)]
+pub struct ChangeList {
+    regions: Vec<Region>,
+    seq: usize,
+}
+
+impl ChangeList {
+    pub fn new() -> ChangeList {
+        ChangeList::default()
+    }
+
+    /// Records an edit replacing `iv` with `new_len` bytes of text.
+    pub fn after_edit(&mut self, iv: Interval, new_len: usize) {
+        let (start, end) = iv.start_end();
+        let transform = |offset: usize| {
+            if offset <= start {
+                offset
+            } else if offset >= end {
+                offset - (end - start) + new_len
+            } else {
+                start + new_len
+            }
+        };
+        let mut edited = Region { start: start, end: start + new_len, seq: self.seq };
+        self.seq += 1;
+        let mut regions = Vec::with_capacity(self.regions.len() + 1);
+        for region in &self.regions {
+            let (r_start, r_end) = (transform(region.start), transform(region.end));
+            if r_end < edited.start || r_start > edited.end {
+                regions.push(Region { start: r_start, end: r_end, seq: region.seq });
+            } else {
+                edited.start = min(edited.start, r_start);
+                edited.end = max(edited.end, r_end);
+            }
+        }
+        let ix = regions.iter().position(|r| r.start > edited.end).unwrap_or(regions.len());
+        regions.insert(ix, edited);
+        if regions.len() > MAX_CHANGES {
+            let oldest = (0..regions.len()).min_by_key(|&i| regions[i].seq).unwrap();
+            regions.remove(oldest);
+        }
+        self.regions = regions;
+    }
+
+    /// The edited regions, in order.
+    pub fn ranges(&self) -> Vec<(usize, usize)> {
+        self.regions.iter().map(|r| (r.start, r.end)).collect()
+    }
+
+    /// The start of the first edited region after `offset`.
+    pub fn next_after(&self, offset: usize) -> Option<usize> {
+        self.regions.iter().find(|r| r.start > offset).map(|r| r.start)
+    }
+
+    /// The start of the last edited region before `offset`.
+    pub fn prev_before(&self, offset: usize) -> Option<usize> {
+        self.regions.iter().rev().find(|r| r.start < offset).map(|r| r.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xi_rope::interval::Interval;
+    use changes::ChangeList;
+
+    fn edit(changes: &mut ChangeList, start: usize, end: usize, new_len: usize) {
+        changes.after_edit(Interval::new_closed_open(start, end), new_len);
+    }
+
+    fn starts(changes: &ChangeList) -> Vec<usize> {
+        let mut result = Vec::new();
+        let mut offset = 0;
+        if changes.prev_before(1) == Some(0) {
+            result.push(0);
+        }
+        while let Some(next) = changes.next_after(offset) {
+            result.push(next);
+            offset = next;
+        }
+        result
+    }
+
+    #[test]
+    fn regions_follow_edits() {
+        let mut changes = ChangeList::new();
+        edit(&mut changes, 10, 10, 3);
+        edit(&mut changes, 20, 25, 0);
+        assert_eq!(vec![10, 20], starts(&changes));
+        // an insertion before moves both
+        edit(&mut changes, 0, 0, 2);
+        assert_eq!(vec![0, 12, 22], starts(&changes));
+        // typing at the end of a region extends it rather than adding another
+        edit(&mut changes, 15, 15, 1);
+        assert_eq!(vec![0, 12, 23], starts(&changes));
+        // deleting across two regions merges them
+        edit(&mut changes, 14, 24, 0);
+        assert_eq!(vec![0, 12], starts(&changes));
+        assert_eq!(Some(12), changes.prev_before(20));
+        assert_eq!(None, changes.next_after(12));
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-editor settings, changed by the front-end with `set_config`.
+
+use serde_json::Value;
+
+use file_transform::FileTransform;
+
+/// The settings of one editor.
+#[derive(Clone)]
+pub struct Config {
+    /// Whether typing whitespace after a word starts a new undo group, so that
+    /// undo removes typed text a word at a time.
+    pub undo_word_boundaries: bool,
+    /// Typing after a pause of this many milliseconds starts a new undo group;
+    /// 0 disables this.
+    pub undo_group_timeout_ms: u64,
+    /// The number of columns between tab stops, for display columns.
+    pub tab_size: usize,
+    /// Whether moving to the left end of a line goes to its first non-blank
+    /// character, and only then to column 0.
+    pub smart_home: bool,
+    /// Whether moving to the right end of a line stops before trailing
+    /// whitespace, and only then goes to the true end.
+    pub smart_end: bool,
+    /// Whether saving adds a newline to the file if the text doesn't end with
+    /// one. The buffer itself is left unchanged.
+    pub ensure_final_newline: bool,
+    /// A hint of the language of the text, such as `"rust"`, used for defaults
+    /// like comment delimiters.
+    pub language: Option<String>,
+    /// The largest file, in bytes, that `open` will load; 0 means no limit.
+    pub max_open_bytes: u64,
+    /// Milliseconds without edits after which a modified buffer with a path
+    /// is saved; 0 disables auto-save.
+    pub auto_save_interval: u64,
+    /// Transforms run, in order, on the text of a file as it is opened.
+    pub open_transforms: Vec<FileTransform>,
+    /// Transforms run, in order, on the text as it is saved, before
+    /// `ensure_final_newline`. The buffer itself is left unchanged.
+    pub save_transforms: Vec<FileTransform>,
+    /// Whether saving removes trailing whitespace from the lines edited since
+    /// the file was opened, before the save transforms. The buffer itself is
+    /// left unchanged.
+    pub trim_edited_lines: bool,
+    /// Whether `filter_selection` may run shell commands.
+    pub allow_filter_commands: bool,
+    /// Whether rendered lines mark their trailing whitespace.
+    pub show_trailing_whitespace: bool,
+    /// Whether discarding unreachable undo history waits until no requests
+    /// are waiting, rather than running after every command.
+    pub defer_undo_gc: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            undo_word_boundaries: true,
+            undo_group_timeout_ms: 0,
+            tab_size: 4,
+            smart_home: false,
+            smart_end: false,
+            ensure_final_newline: false,
+            language: None,
+            max_open_bytes: 0,
+            auto_save_interval: 0,
+            open_transforms: Vec::new(),
+            save_transforms: Vec::new(),
+            trim_edited_lines: false,
+            allow_filter_commands: false,
+            show_trailing_whitespace: false,
+            defer_undo_gc: false,
+        }
+    }
+}
+
+/// A change to a single setting.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Setting {
+    UndoWordBoundaries(bool),
+    UndoGroupTimeoutMs(u64),
+    TabSize(usize),
+    SmartHome(bool),
+    SmartEnd(bool),
+    EnsureFinalNewline(bool),
+    Language(Option<String>),
+    MaxOpenBytes(u64),
+    AutoSaveInterval(u64),
+    OpenTransforms(Vec<FileTransform>),
+    SaveTransforms(Vec<FileTransform>),
+    TrimEditedLines(bool),
+    AllowFilterCommands(bool),
+    ShowTrailingWhitespace(bool),
+    DeferUndoGc(bool),
+}
+
+impl Setting {
+    /// Parses one `key: value` member of the `set_config` parameters.
+    pub fn from_json(key: &str, value: &Value) -> Option<Setting> {
+        use self::Setting::*;
+
+        match key {
+            "undo_word_boundaries" => value.as_boolean().map(UndoWordBoundaries),
+            "undo_group_timeout_ms" => value.as_u64().map(UndoGroupTimeoutMs),
+            "tab_size" => value.as_u64()
+                .and_then(|n| if n > 0 { Some(TabSize(n as usize)) } else { None }),
+            "smart_home" => value.as_boolean().map(SmartHome),
+            "smart_end" => value.as_boolean().map(SmartEnd),
+            "ensure_final_newline" => value.as_boolean().map(EnsureFinalNewline),
+            "language" => match *value {
+                Value::Null => Some(Language(None)),
+                Value::String(ref s) => Some(Language(Some(s.to_lowercase()))),
+                _ => None,
+            },
+            "max_open_bytes" => value.as_u64().map(MaxOpenBytes),
+            "auto_save_interval" => value.as_u64().map(AutoSaveInterval),
+            "open_transforms" => transforms_from_json(value).map(OpenTransforms),
+            "save_transforms" => transforms_from_json(value).map(SaveTransforms),
+            "trim_edited_lines" => value.as_boolean().map(TrimEditedLines),
+            "allow_filter_commands" => value.as_boolean().map(AllowFilterCommands),
+            "show_trailing_whitespace" => value.as_boolean().map(ShowTrailingWhitespace),
+            "defer_undo_gc" => value.as_boolean().map(DeferUndoGc),
+            _ => None,
+        }
+    }
+}
+
+// Parses an array of transform names, such as `["normalize_crlf"]`.
+fn transforms_from_json(value: &Value) -> Option<Vec<FileTransform>> {
+    value.as_array().and_then(|names| {
+        names.iter()
+            .map(|name| name.as_string().and_then(FileTransform::from_name))
+            .collect()
+    })
+}
+
+impl Config {
+    pub fn apply(&mut self, setting: Setting) {
+        use self::Setting::*;
+
+        match setting {
+            UndoWordBoundaries(b) => self.undo_word_boundaries = b,
+            UndoGroupTimeoutMs(ms) => self.undo_group_timeout_ms = ms,
+            TabSize(n) => self.tab_size = n,
+            SmartHome(b) => self.smart_home = b,
+            SmartEnd(b) => self.smart_end = b,
+            EnsureFinalNewline(b) => self.ensure_final_newline = b,
+            Language(language) => self.language = language,
+            MaxOpenBytes(n) => self.max_open_bytes = n,
+            AutoSaveInterval(ms) => self.auto_save_interval = ms,
+            OpenTransforms(transforms) => self.open_transforms = transforms,
+            SaveTransforms(transforms) => self.save_transforms = transforms,
+            TrimEditedLines(b) => self.trim_edited_lines = b,
+            AllowFilterCommands(b) => self.allow_filter_commands = b,
+            ShowTrailingWhitespace(b) => self.show_trailing_whitespace = b,
+            DeferUndoGc(b) => self.defer_undo_gc = b,
+        }
+    }
+}
+
+/// The block comment delimiters of some common languages.
+pub fn block_comment_delimiters(language: &str) -> Option<(&'static str, &'static str)> {
+    match language {
+        "c" | "cpp" | "css" | "go" | "java" | "javascript" | "rust" | "swift" |
+        "typescript" => Some(("/*", "*/")),
+        "html" | "markdown" | "xml" => Some(("<!--", "-->")),
+        "haskell" => Some(("{-", "-}")),
+        "lua" => Some(("--[[", "]]")),
+        "ocaml" => Some(("(*", "*)")),
+        _ => None,
+    }
+}
+
+/// The line comment prefixes of some common languages.
+pub fn line_comment_prefix(language: &str) -> Option<&'static str> {
+    match language {
+        "c" | "cpp" | "go" | "java" | "javascript" | "rust" | "swift" |
+        "typescript" => Some("//"),
+        "python" | "ruby" | "shell" | "toml" | "yaml" => Some("#"),
+        "haskell" | "lua" | "sql" => Some("--"),
+        "lisp" | "scheme" => Some(";"),
+        _ => None,
+    }
+}
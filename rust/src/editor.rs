@@ -12,23 +12,39 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::cmp::max;
+use std::cmp::{min, max};
+use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::{Read, Write};
-use std::collections::BTreeSet;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::SystemTime;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use serde_json::Value;
+use serde_json::builder::ObjectBuilder;
+use time;
 
-use xi_rope::rope::{LinesMetric, Rope};
+use xi_rope::rope::{BaseMetric, LinesMetric, Rope, RopeInfo};
 use xi_rope::interval::Interval;
-use xi_rope::delta::Delta;
+use xi_rope::delta::{Delta, DeltaBuilder};
 use xi_rope::tree::Cursor;
 use xi_rope::engine::Engine;
 use xi_rope::spans::SpansBuilder;
-use view::{Style, View};
+use view::{RecenterPosition, Style, View, WrapMode, count_lines, display_width};
 
 use tabs::TabCtx;
-use rpc::EditCommand;
+use rpc::{EditCommand, EditMotion, EscapeKind, Granularity, LineEnding, LocationStyle};
 use run_plugin::start_plugin;
+use words::{WordCursor, count_words};
+use transform;
+use scope;
+use find;
+use config;
+use config::{Config, Setting};
+use changes::ChangeList;
+use file_transform;
 
 const FLAG_SELECT: u64 = 2;
 
@@ -36,30 +52,153 @@ const MAX_UNDOS: usize = 20;
 
 const TAB_SIZE: usize = 4;
 
+// the most text `duplicate` inserts at once, in bytes
+const MAX_DUPLICATE_BYTES: usize = 1 << 24;
+
+// texts at least this long, in bytes, are saved by a worker thread, which sends
+// a progress notification each time it has written another SAVE_PROGRESS_BYTES
+const STREAMING_SAVE_BYTES: usize = 1 << 24;
+const SAVE_PROGRESS_BYTES: usize = 1 << 22;
+
+// Engine priorities, which order concurrent inserts at the same position: the
+// one with the higher priority goes after. User edits all have one priority
+// (they are never concurrent with each other), and plugin edits are in the band
+// below it, from PLUGIN_EDIT_PRIORITY, so that text a plugin inserts where the
+// user is typing always ends up before the typed text. Each plugin started gets
+// the next priority in the band, unless it passes its own with an edit.
+const USER_EDIT_PRIORITY: usize = 0x10000;
+pub const PLUGIN_EDIT_PRIORITY: usize = 0x8000;
+
+/// The edit priority of the `n`th plugin started, wrapping around the band.
+pub fn plugin_edit_priority(n: usize) -> usize {
+    PLUGIN_EDIT_PRIORITY + n % (USER_EDIT_PRIORITY - PLUGIN_EDIT_PRIORITY)
+}
+
+/// Whether a plugin may give its edits `priority`.
+pub fn is_plugin_edit_priority(priority: usize) -> bool {
+    (PLUGIN_EDIT_PRIORITY..USER_EDIT_PRIORITY).contains(&priority)
+}
+
+static LOG_TIMING: AtomicBool = AtomicBool::new(false);
+
+/// Selects whether each edit command logs how long it took, for profiling.
+pub fn set_log_timing(log_timing: bool) {
+    LOG_TIMING.store(log_timing, Ordering::Relaxed);
+}
+
+/// What the auto-save timer should do next.
+pub enum AutoSave {
+    /// Sleep for this many more milliseconds, as the text was edited since.
+    Wait(u64),
+    /// The file at the path was saved, with the result of `save`.
+    Saved(String, Value),
+    /// The text is too large to save under the lock, so the timer writes it
+    /// to the path: the text of the revision, and whether to end it with a
+    /// newline.
+    Streaming(String, Rope, usize, bool),
+    /// Auto-save no longer applies, for example because the buffer was saved.
+    Cancelled,
+}
+
+// The selections around the edits of one undo group.
+struct UndoSelections {
+    before: (usize, usize),
+    // None until a command's edits in the group are committed
+    after: Option<(usize, usize)>,
+}
+
+// A text-changing command, as recorded for repeat_last_edit.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum RepeatableEdit {
+    Insert(String),
+    InsertNewline,
+    InsertTab,
+    DeleteForward,
+    DeleteBackward,
+    DeleteToBeginningOfLine,
+    Delete(EditMotion),
+}
+
 pub struct Editor {
     text: Rope,
     view: View,
 
     engine: Engine,
     last_rev_id: usize,
+    // added to engine revision ids so the id reported to the front-end keeps
+    // increasing when the engine is replaced by reset_contents
+    rev_id_base: usize,
     undo_group_id: usize,
     live_undos: Vec<usize>, //  undo groups that may still be toggled
     cur_undo: usize, // index to live_undos, ones after this are undone
     undos: BTreeSet<usize>, // undo groups that are undone
     gc_undos: BTreeSet<usize>, // undo groups that are no longer live and should be gc'ed
+    // the selections before and after the edits of each live undo group, for
+    // undo and redo to restore
+    undo_selections: BTreeMap<usize, UndoSelections>,
 
     this_edit_type: EditType,
     last_edit_type: EditType,
+    // the undo group of the edits made so far by the current command
+    command_undo_group: Option<usize>,
+    // while set, motions extend the selection from here, as in Emacs
+    mark: Option<usize>,
+    // when the text was last changed, in ns, for auto-save
+    last_edit_time: u64,
+    // whether an auto-save timer is running for this editor
+    auto_save_pending: bool,
 
     // update to cursor, to be committed atomically with delta
     // TODO: use for all cursor motion?
     new_cursor: Option<(usize, usize)>,
 
+    // selections before and after each expand_selection step, innermost first;
+    // only valid while the current selection is the last one
+    selection_stack: Vec<(usize, usize)>,
+
+    // the unit drags extend the selection by
+    drag_granularity: Granularity,
+
+    // the last text-changing command that repeat_last_edit can repeat, and the
+    // undo group of its edits, so that typing continuing it adds to its text
+    last_edit: Option<RepeatableEdit>,
+    last_edit_group: Option<usize>,
+
+    // the regions edited since the text was opened, for next_change
+    changes: ChangeList,
+    // the end of the most recent edit, for goto_last_edit
+    last_edit_offset: Option<usize>,
+
+    config: Config,
+    // the file last opened or saved, if any
+    path: Option<String>,
+    // head_rev_id() when the text last matched that file
+    pristine_rev_id: usize,
+    // the size and modification time of the file when save last wrote it,
+    // while what save would write is known to be unchanged since
+    disk_stamp: Option<(u64, SystemTime)>,
+    // whether that file is read-only, so save needs force
+    read_only: bool,
+    // whether a save is being written by a worker thread
+    saving: bool,
+    // time of the last typed insertion, for undo_group_timeout_ms
+    last_insert_time: u64,
+    insert_mode: InsertMode,
+
     dirty: bool,
     scroll_to: Option<usize>,
+    // a line the front-end should scroll to the top of the window
+    scroll_top: Option<usize>,
     col: usize, // maybe this should live in view, it's similar to selection
 }
 
+// Whether typed text is inserted, or replaces the text after the cursor.
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum InsertMode {
+    Insert,
+    Overwrite,
+}
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 enum EditType {
     Other,
@@ -70,35 +209,115 @@ enum EditType {
 
 impl Editor {
     pub fn new() -> Editor {
-        let engine = Engine::new(Rope::from(""));
+        Editor::with_text(Rope::from(""))
+    }
+
+    /// Creates an untitled editor whose buffer starts out with `text`.
+    pub fn with_text(text: Rope) -> Editor {
+        let engine = Engine::new(text.clone());
         let last_rev_id = engine.get_head_rev_id();
         Editor {
-            text: Rope::from(""),
+            text: text,
             view: View::new(),
             dirty: false,
             engine: engine,
             last_rev_id: last_rev_id,
+            rev_id_base: 0,
             undo_group_id: 0,
             live_undos: Vec::new(),
             cur_undo: 0,
             undos: BTreeSet::new(),
             gc_undos: BTreeSet::new(),
+            undo_selections: BTreeMap::new(),
             last_edit_type: EditType::Other,
             this_edit_type: EditType::Other,
+            command_undo_group: None,
+            mark: None,
+            last_edit_time: 0,
+            auto_save_pending: false,
             new_cursor: None,
+            selection_stack: Vec::new(),
+            drag_granularity: Granularity::Char,
+            last_edit: None,
+            last_edit_group: None,
+            changes: ChangeList::new(),
+            last_edit_offset: None,
+            config: Config::default(),
+            path: None,
+            pristine_rev_id: last_rev_id,
+            disk_stamp: None,
+            read_only: false,
+            saving: false,
+            last_insert_time: 0,
+            insert_mode: InsertMode::Insert,
             scroll_to: Some(0),
+            scroll_top: None,
             col: 0,
         }
     }
 
+    /// An untitled editor starting with a copy of this one's text, selection,
+    /// scroll position and settings, but with its own undo history.
+    pub fn duplicate(&self) -> Editor {
+        let mut editor = Editor::with_text(self.text.clone());
+        editor.view = self.view.duplicate(&self.text);
+        editor.config = self.config.clone();
+        editor.insert_mode = self.insert_mode;
+        editor.col = self.col;
+        editor.scroll_to = Some(self.view.sel_end);
+        editor
+    }
+
     fn insert(&mut self, s: &str) {
         let sel_interval = Interval::new_closed_open(self.view.sel_min(), self.view.sel_max());
-        let new_cursor = self.view.sel_min() + s.len();
-        self.add_delta(sel_interval, Rope::from(s), new_cursor, new_cursor);
+        self.insert_over(sel_interval, s);
+    }
+
+    // Replaces `iv`, which starts at the selection, with `s`.
+    fn insert_over(&mut self, iv: Interval, s: &str) {
+        if self.this_edit_type == EditType::InsertChars && self.starts_undo_group(s) {
+            // forces add_delta to start a new undo group
+            self.last_edit_type = EditType::Other;
+        }
+        let new_cursor = iv.start() + s.len();
+        self.add_delta(iv, Rope::from(s), new_cursor, new_cursor);
     }
 
+    // Whether typing `s` should not be merged into the previous undo group:
+    // after a pause, or at whitespace following a word.
+    fn starts_undo_group(&mut self, s: &str) -> bool {
+        let now = time::precise_time_ns();
+        let elapsed_ms = (now - self.last_insert_time) / 1_000_000;
+        self.last_insert_time = now;
+        if self.config.undo_group_timeout_ms > 0 && elapsed_ms >= self.config.undo_group_timeout_ms {
+            return true;
+        }
+        let after_word = match self.text.prev_codepoint_offset(self.view.sel_min()) {
+            Some(prev) => !self.text.slice_to_string(prev, self.view.sel_min())
+                .chars().all(char::is_whitespace),
+            None => false,
+        };
+        self.config.undo_word_boundaries && after_word && s.starts_with(char::is_whitespace)
+    }
+
+    // Moves the cursor to `offset`. When the current command modifies the
+    // selection, the anchor stays put, otherwise the selection collapses.
     fn set_cursor(&mut self, offset: usize, hard: bool) {
-        if self.this_edit_type != EditType::Select {
+        let set_start = self.this_edit_type != EditType::Select;
+        self.set_cursor_impl(offset, set_start, hard);
+    }
+
+    // Sets the anchor and the cursor independently of the current command.
+    fn set_selection(&mut self, start: usize, end: usize) {
+        self.mark = None;
+        self.view.sel_start = start;
+        self.set_cursor_impl(end, false, true);
+    }
+
+    // Moves the cursor to `offset`, and the anchor too if `set_start`. A hard
+    // move also resets the goal column and scrolls to the cursor.
+    fn set_cursor_impl(&mut self, offset: usize, set_start: bool, hard: bool) {
+        if set_start {
             self.view.sel_start = offset;
         }
         self.view.sel_end = offset;
@@ -116,10 +335,27 @@ impl Editor {
     // will need more information, for example to decide whether to merge undos.
     fn add_delta(&mut self, iv: Interval, new: Rope, new_start: usize, new_end: usize) {
         let delta = Delta::simple_edit(iv, new, self.text.len());
+        self.add_full_delta(delta, new_start, new_end);
+    }
+
+    // Like add_delta, but for deltas that may edit several regions at once.
+    // A command may add any number of deltas, each applying to the text as left
+    // by the previous one; they all go into one undo group.
+    fn add_full_delta(&mut self, delta: Delta<RopeInfo>, new_start: usize, new_end: usize) {
         let head_rev_id = self.engine.get_head_rev_id();
+        self.add_delta_at(USER_EDIT_PRIORITY, head_rev_id, delta);
+        self.new_cursor = Some((new_start, new_end));
+    }
+
+    // Adds a delta against engine revision `base_rev` with the given priority,
+    // choosing its undo group.
+    fn add_delta_at(&mut self, priority: usize, base_rev: usize, delta: Delta<RopeInfo>) {
         let undo_group;
 
-        if self.this_edit_type == self.last_edit_type &&
+        if let Some(group) = self.command_undo_group {
+            // an earlier edit by the same command
+            undo_group = group;
+        } else if self.this_edit_type == self.last_edit_type &&
             self.this_edit_type != EditType::Other &&
             self.this_edit_type != EditType::Select &&
             !self.live_undos.is_empty() {
@@ -130,6 +366,10 @@ impl Editor {
             self.gc_undos.extend(&self.live_undos[self.cur_undo..]);
             self.live_undos.truncate(self.cur_undo);
             self.live_undos.push(undo_group);
+            self.undo_selections.insert(undo_group, UndoSelections {
+                before: (self.view.sel_start, self.view.sel_end),
+                after: None,
+            });
             if self.live_undos.len() <= MAX_UNDOS {
                 self.cur_undo += 1;
             } else {
@@ -137,11 +377,10 @@ impl Editor {
             }
             self.undo_group_id += 1;
         }
+        self.command_undo_group = Some(undo_group);
         self.last_edit_type = self.this_edit_type;
-        let priority = 0x10000;
-        self.engine.edit_rev(priority, undo_group, head_rev_id, delta);
+        self.engine.edit_rev(priority, undo_group, base_rev, delta);
         self.text = self.engine.get_head();
-        self.new_cursor = Some((new_start, new_end));
     }
 
     // commit the current delta, updating views and other invariants as needed
@@ -149,8 +388,13 @@ impl Editor {
         if self.engine.get_head_rev_id() != self.last_rev_id {
             self.update_after_revision();
             if let Some((start, end)) = self.new_cursor.take() {
-                self.set_cursor(end, true);
-                self.view.sel_start = start;
+                self.set_selection(start, end);
+            }
+            let selection = (self.view.sel_start, self.view.sel_end);
+            if let Some(group) = self.command_undo_group {
+                if let Some(selections) = self.undo_selections.get_mut(&group) {
+                    selections.after = Some(selection);
+                }
             }
         }
     }
@@ -164,7 +408,11 @@ impl Editor {
     fn update_after_revision(&mut self) {
         let delta = self.engine.delta_rev_head(self.last_rev_id);
         self.view.after_edit(&self.text, &delta);
+        let (iv, new_len) = delta.summary();
+        self.changes.after_edit(iv, new_len);
+        self.last_edit_offset = Some(iv.start() + new_len);
         self.last_rev_id = self.engine.get_head_rev_id();
+        self.mark = None;
         self.dirty = true;
     }
 
@@ -172,24 +420,50 @@ impl Editor {
         if !self.gc_undos.is_empty() {
             self.engine.gc(&self.gc_undos);
             self.undos = &self.undos - &self.gc_undos;
+            for group in &self.gc_undos {
+                self.undo_selections.remove(group);
+            }
             self.gc_undos.clear();
         }
     }
 
+    /// Discards the undo history that gc was deferred for, with
+    /// `defer_undo_gc`. Nothing is pending otherwise.
+    pub fn flush_undo_gc(&mut self) {
+        self.gc_undos();
+    }
+
     fn reset_contents(&mut self, new_contents: Rope) {
+        self.rev_id_base = self.head_rev_id() + 1;
         self.engine = Engine::new(new_contents);
+        self.last_rev_id = self.engine.get_head_rev_id();
         self.text = self.engine.get_head();
         self.dirty = true;
-        self.view.reset_breaks();
+        self.view.after_reset(&self.text);
+        self.changes = ChangeList::new();
+        self.last_edit_offset = None;
+        self.mark = None;
         self.set_cursor(0, true);
     }
 
+    /// The revision id of the current text, as reported to the front-end. It
+    /// strictly increases with every edit, undo, redo and open.
+    fn head_rev_id(&self) -> usize {
+        self.rev_id_base + self.engine.get_head_rev_id()
+    }
+
     // render if needed, sending to ui
     pub fn render(&mut self, tab_ctx: &TabCtx) {
         if self.dirty {
-            tab_ctx.update_tab(&self.view.render(&self.text, self.scroll_to));
+            self.view.update_find(&self.text);
+            let rev = self.head_rev_id();
+            let overwrite = self.insert_mode == InsertMode::Overwrite;
+            tab_ctx.update_tab(&self.view.render(&self.text, rev, self.scroll_to,
+                                                    self.scroll_top, self.config.tab_size,
+                                                    overwrite));
             self.dirty = false;
             self.scroll_to = None;
+            self.scroll_top = None;
         }
     }
 
@@ -212,10 +486,76 @@ impl Editor {
         self.delete();
     }
 
+    // Deletes back to the first non-blank character of the line, or to column 0
+    // if the cursor is already within the indentation. At column 0 this joins
+    // with the previous line, like a backspace.
     fn delete_to_beginning_of_line(&mut self) {
-        self.move_to_left_end_of_line(FLAG_SELECT);
+        if self.view.sel_start != self.view.sel_end {
+            self.delete();
+            return;
+        }
 
-        self.delete();
+        let end = self.view.sel_end;
+        let (_, col) = self.view.offset_to_line_col(&self.text, end);
+        let line_start = end - col;
+        let indent_end = self.first_non_blank(line_start);
+        let start = if indent_end < end { indent_end } else { line_start };
+
+        if start < end {
+            let del_interval = Interval::new_closed_open(start, end);
+            self.add_delta(del_interval, Rope::from(""), start, start);
+        } else {
+            self.delete();
+        }
+    }
+
+    // offset of the first character at or after `offset` that isn't a space or tab
+    fn first_non_blank(&self, mut offset: usize) -> usize {
+        while offset < self.text.len() {
+            match self.text.byte_at(offset) {
+                b' ' | b'\t' => offset += 1,
+                _ => break,
+            }
+        }
+        offset
+    }
+
+    // Deletes the selection if there is one, otherwise from the cursor to `offset`.
+    fn delete_to(&mut self, offset: usize) {
+        let (start, end) = if self.view.sel_start != self.view.sel_end {
+            (self.view.sel_min(), self.view.sel_max())
+        } else {
+            (min(offset, self.view.sel_end), max(offset, self.view.sel_end))
+        };
+        if start < end {
+            self.this_edit_type = EditType::Delete;
+            let del_interval = Interval::new_closed_open(start, end);
+            self.add_delta(del_interval, Rope::from(""), start, start);
+        }
+    }
+
+    fn do_delete(&mut self, motion: EditMotion) {
+        use rpc::EditMotion::*;
+
+        let sel_end = self.view.sel_end;
+        match motion {
+            PrevChar => self.delete_backward(),
+            NextChar => self.delete_forward(),
+            PrevWord | PrevSubword => {
+                let offset = WordCursor::new(&self.text, sel_end).prev_boundary(motion == PrevSubword);
+                self.delete_to(offset);
+            }
+            NextWord | NextSubword => {
+                let offset = WordCursor::new(&self.text, sel_end).next_boundary(motion == NextSubword);
+                self.delete_to(offset);
+            }
+            StartOfLine => self.delete_to_beginning_of_line(),
+            PrevLine | NextLine => {
+                let line_delta = if motion == PrevLine { -1 } else { 1 };
+                let offset = self.view.vertical_motion(&self.text, line_delta, self.col);
+                self.delete_to(offset);
+            }
+        }
     }
 
     fn delete(&mut self) {
@@ -271,6 +611,19 @@ impl Editor {
         }
     }
 
+    // Sets the mark at the cursor, so that motions select from there.
+    fn set_mark(&mut self) {
+        let offset = self.view.sel_end;
+        self.set_cursor_impl(offset, true, false);
+        self.mark = Some(offset);
+    }
+
+    fn clear_mark(&mut self) {
+        let offset = self.view.sel_end;
+        self.set_cursor_impl(offset, true, false);
+        self.mark = None;
+    }
+
     fn modify_selection(&mut self) {
         self.this_edit_type = EditType::Select;
     }
@@ -280,9 +633,9 @@ impl Editor {
             self.modify_selection();
         }
 
-        let old_offset = self.view.sel_end;
+        // not hard, so the goal column survives hitting the top of the document
         let offset = self.view.vertical_motion(&self.text, -1, self.col);
-        self.set_cursor(offset, old_offset == offset);
+        self.set_cursor(offset, false);
         self.scroll_to = Some(offset);
     }
 
@@ -291,9 +644,8 @@ impl Editor {
             self.modify_selection();
         }
 
-        let old_offset = self.view.sel_end;
         let offset = self.view.vertical_motion(&self.text, 1, self.col);
-        self.set_cursor(offset, old_offset == offset);
+        self.set_cursor(offset, false);
         self.scroll_to = Some(offset);
     }
 
@@ -326,7 +678,15 @@ impl Editor {
         }
 
         let line_col = self.view.offset_to_line_col(&self.text, self.view.sel_end);
-        let offset = self.view.line_col_to_offset(&self.text, line_col.0, 0);
+        let mut offset = self.view.line_col_to_offset(&self.text, line_col.0, 0);
+        if self.config.smart_home {
+            // toggle between the first non-blank character and column 0
+            let first_non_blank = self.first_non_blank(offset);
+            if first_non_blank != self.view.sel_end && line_col.0 ==
+                self.view.offset_to_line_col(&self.text, first_non_blank).0 {
+                offset = first_non_blank;
+            }
+        }
 
         self.set_cursor(offset, true);
 
@@ -370,6 +730,18 @@ impl Editor {
                 offset = prev;
             }
         }
+        if self.config.smart_end {
+            // toggle between the end of the text and the true end of the line
+            let line_start = self.view.line_col_to_offset(&self.text, line_col.0, 0);
+            let mut text_end = offset;
+            while text_end > line_start && (self.text.byte_at(text_end - 1) == b' ' ||
+                                            self.text.byte_at(text_end - 1) == b'\t') {
+                text_end -= 1;
+            }
+            if text_end != self.view.sel_end {
+                offset = text_end;
+            }
+        }
 
         self.set_cursor(offset, true);
 
@@ -432,9 +804,8 @@ impl Editor {
         }
 
         let scroll = -max(self.view.scroll_height() as isize - 2, 1);
-        let old_offset = self.view.sel_end;
         let offset = self.view.vertical_motion(&self.text, scroll, self.col);
-        self.set_cursor(offset, old_offset == offset);
+        self.set_cursor(offset, false);
         let scroll_offset = self.view.vertical_motion(&self.text, scroll, self.col);
         self.scroll_to = Some(scroll_offset);
     }
@@ -445,9 +816,8 @@ impl Editor {
         }
 
         let scroll = max(self.view.scroll_height() as isize - 2, 1);
-        let old_offset = self.view.sel_end;
         let offset = self.view.vertical_motion(&self.text, scroll, self.col);
-        self.set_cursor(offset, old_offset == offset);
+        self.set_cursor(offset, false);
         let scroll_offset = self.view.vertical_motion(&self.text, scroll, self.col);
         self.scroll_to = Some(scroll_offset);
     }
@@ -490,7 +860,7 @@ impl Editor {
                 // F2, but using for debugging
                 self.debug_test_fg_spans();
             }
-            _ => self.insert(chars),
+            _ => self.do_insert(chars),
         }
     }
 
@@ -498,37 +868,525 @@ impl Editor {
     // but paste should.
     fn do_insert(&mut self, chars: &str) {
         self.this_edit_type = EditType::InsertChars;
-        self.insert(chars);
+        self.insert_typed(chars);
     }
 
-    fn do_open(&mut self, path: &str) {
-        match File::open(path) {
-            Ok(mut f) => {
-                let mut s = String::new();
-                if f.read_to_string(&mut s).is_ok() {
-                    self.reset_contents(Rope::from(s));
-                }
+    // Inserts typed text. A closing bracket typed at the start of a line also
+    // outdents the line to match the line of its opening bracket.
+    fn insert_typed(&mut self, chars: &str) {
+        match self.closing_bracket_indent(chars) {
+            Some((line_start, indent)) => {
+                let iv = Interval::new_closed_open(line_start, self.view.sel_end);
+                let new_cursor = line_start + indent.len() + chars.len();
+                self.add_delta(iv, Rope::from(indent + chars), new_cursor, new_cursor);
+            }
+            None if self.insert_mode == InsertMode::Overwrite &&
+                self.view.sel_start == self.view.sel_end && !chars.contains('\n') => {
+                let iv = Interval::new_closed_open(self.view.sel_end, self.overwrite_end(chars));
+                self.insert_over(iv, chars);
             }
-            Err(e) => print_err!("error {}", e),
+            None => self.insert(chars),
         }
     }
 
-    fn do_save(&mut self, path: &str) {
-        match File::create(path) {
-            Ok(mut f) => {
-                for chunk in self.text.iter_chunks(0, self.text.len()) {
-                    if let Err(e) = f.write_all(chunk.as_bytes()) {
-                        print_err!("write error {}", e);
-                        break;
-                    }
-                }
+    // The end of the text that typing `chars` replaces in overwrite mode: one
+    // grapheme for each character typed, but not beyond the end of the line.
+    fn overwrite_end(&self, chars: &str) -> usize {
+        let mut end = self.view.sel_end;
+        for _ in chars.chars() {
+            if end == self.text.len() || self.text.byte_at(end) == b'\n' ||
+                self.text.byte_at(end) == b'\r' {
+                break;
+            }
+            end = self.text.next_grapheme_offset(end).unwrap_or(self.text.len());
+        }
+        end
+    }
+
+    fn toggle_overwrite(&mut self) {
+        self.insert_mode = match self.insert_mode {
+            InsertMode::Insert => InsertMode::Overwrite,
+            InsertMode::Overwrite => InsertMode::Insert,
+        };
+        self.dirty = true;
+    }
+
+    // If typing `chars` should reindent the cursor's line, the start of the line
+    // and its new indentation.
+    fn closing_bracket_indent(&self, chars: &str) -> Option<(usize, String)> {
+        if self.view.sel_start != self.view.sel_end || chars.len() != 1 ||
+            !(chars == ")" || chars == "]" || chars == "}") {
+            return None;
+        }
+        let cursor = self.view.sel_end;
+        let line_start = self.text.offset_of_line(self.text.line_of_offset(cursor));
+        let old_indent = self.text.slice_to_string(line_start, cursor);
+        if !old_indent.bytes().all(|b| b == b' ' || b == b'\t') {
+            return None;
+        }
+        let open = scope::unmatched_open_before(&self.text, cursor)
+            .filter(|&open| scope::closer_of(self.text.byte_at(open)) == Some(chars.as_bytes()[0]));
+        open.and_then(|open| {
+            let open_line = self.text.line_of_offset(open);
+            let open_line_start = self.text.offset_of_line(open_line);
+            let indent: String = self.text.slice_to_string(open_line_start, open).chars()
+                .take_while(|&c| c == ' ' || c == '\t')
+                .collect();
+            if open_line_start == line_start || indent == old_indent {
+                None
+            } else {
+                Some((line_start, indent))
+            }
+        })
+    }
+
+    // Inserts the current local time. An empty or invalid format falls back to
+    // ISO 8601 (RFC 3339).
+    fn insert_date_time(&mut self, format: &str) {
+        let now = time::now();
+        let stamp = match time::strftime(format, &now) {
+            Ok(ref s) if !format.is_empty() => s.clone(),
+            _ => now.rfc3339().to_string(),
+        };
+        self.insert(&stamp);
+    }
+
+    // Replaces the selection with `template`, the selected text taking the
+    // place of its `$0`, and puts the cursor after the result.
+    fn wrap_with_template(&mut self, template: &str) {
+        let (start, end) = (self.view.sel_min(), self.view.sel_max());
+        let selected = self.text.slice_to_string(start, end);
+        let filled = transform::fill_template(template, &selected);
+        let new_end = start + filled.len();
+        self.add_delta(Interval::new_closed_open(start, end), Rope::from(filled), new_end, new_end);
+    }
+
+    // Reads the file at `path`, unless it is larger than max_open_bytes, and
+    // runs the open transforms on it. The error is the result to report to the
+    // front-end.
+    fn read_file(&self, path: &str) -> Result<String, Value> {
+        let max_bytes = self.config.max_open_bytes;
+        if max_bytes > 0 {
+            match fs::metadata(path) {
+                Ok(metadata) => if metadata.len() > max_bytes {
+                    return Err(ObjectBuilder::new()
+                        .insert("ok", false)
+                        .insert("error", "too_large")
+                        .insert("message", format!("{} is {} bytes, more than max_open_bytes ({})",
+                            path, metadata.len(), max_bytes))
+                        .unwrap());
+                },
+                Err(e) => return Err(io_error(&e)),
+            }
+        }
+        let mut s = String::new();
+        match File::open(path).and_then(|mut f| f.read_to_string(&mut s)) {
+            Ok(_) if self.config.open_transforms.is_empty() => Ok(s),
+            Ok(_) => Ok(String::from(file_transform::apply_all(&self.config.open_transforms,
+                Rope::from(s), self.config.tab_size))),
+            Err(e) => Err(io_error(&e)),
+        }
+    }
+
+    // Loads the file at `path`, returning `{"ok": true}`, or the reason it
+    // couldn't be opened.
+    fn do_open(&mut self, path: &str, line: usize, col: usize) -> Value {
+        let s = match self.read_file(path) {
+            Ok(s) => s,
+            Err(e) => return e,
+        };
+        self.reset_contents(Rope::from(s));
+        self.path = Some(path.to_string());
+        self.pristine_rev_id = self.head_rev_id();
+        self.disk_stamp = None;
+        self.read_only = is_read_only(path);
+        let offset = self.view.logical_line_col_to_offset(&self.text, line, col);
+        self.set_cursor(offset, true);
+        ObjectBuilder::new()
+            .insert("ok", true)
+            .insert("read_only", self.read_only)
+            .unwrap()
+    }
+
+    // Reloads the file the buffer was opened from or saved to, as a single
+    // undoable edit that replaces only the part that differs, so the cursor
+    // stays put where the text around it is unchanged.
+    fn do_revert(&mut self) -> Value {
+        let path = match self.path.clone() {
+            Some(path) => path,
+            None => return ObjectBuilder::new()
+                .insert("ok", false)
+                .insert("error", "no_path")
+                .insert("message", "the buffer has not been opened from or saved to a file")
+                .unwrap(),
+        };
+        let new = match self.read_file(&path) {
+            Ok(s) => s,
+            Err(e) => return e,
+        };
+        let old = self.text.slice_to_string(0, self.text.len());
+        let prefix = old.char_indices().zip(new.chars())
+            .find(|&((_, a), b)| a != b)
+            .map_or(min(old.len(), new.len()), |((i, _), _)| i);
+        let suffix = old[prefix..].chars().rev().zip(new[prefix..].chars().rev())
+            .take_while(|&(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum::<usize>();
+        let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+        if prefix < old_end || prefix < new_end {
+            let cursor = self.view.sel_end;
+            let mut new_cursor = if cursor <= prefix {
+                cursor
+            } else if cursor >= old_end {
+                cursor - old_end + new_end
+            } else {
+                min(cursor, new_end)
+            };
+            while !new.is_char_boundary(new_cursor) {
+                new_cursor -= 1;
+            }
+            let iv = Interval::new_closed_open(prefix, old_end);
+            self.add_delta(iv, Rope::from(&new[prefix..new_end]), new_cursor, new_cursor);
+        }
+        self.pristine_rev_id = self.head_rev_id();
+        self.disk_stamp = None;
+        self.dirty = true;
+        ObjectBuilder::new().insert("ok", true).unwrap()
+    }
+
+    // Writes the text to `path`, returning `{"ok": true}` or the reason it
+    // wasn't saved. A file that was read-only when opened is only written with
+    // `force`. Texts of at least STREAMING_SAVE_BYTES are handed to a worker
+    // thread, in which case the result only says the save has started.
+    fn do_save(&mut self, path: &str, force: bool, tab_ctx: &TabCtx) -> Value {
+        let text = match self.text_to_save(path, force) {
+            Ok(text) => text,
+            Err(result) => return result,
+        };
+        if text.len() < STREAMING_SAVE_BYTES {
+            return self.save_now(path, &text);
+        }
+        self.saving = true;
+        tab_ctx.start_save(path.to_string(), text, self.head_rev_id(),
+            self.config.ensure_final_newline);
+        ObjectBuilder::new()
+            .insert("ok", true)
+            .insert("pending", true)
+            .unwrap()
+    }
+
+    // The text to write to `path`, after the save transforms, or the result
+    // to report if it can't be saved there now.
+    fn text_to_save(&self, path: &str, force: bool) -> Result<Rope, Value> {
+        if self.saving {
+            return Err(ObjectBuilder::new()
+                .insert("ok", false)
+                .insert("error", "busy")
+                .insert("message", "an earlier save is still being written")
+                .unwrap());
+        }
+        if self.read_only && !force && self.path.as_deref() == Some(path) {
+            return Err(ObjectBuilder::new()
+                .insert("ok", false)
+                .insert("error", "read_only")
+                .insert("message", format!("{} is read-only", path))
+                .unwrap());
+        }
+        Ok(self.transformed_for_save())
+    }
+
+    // The text after the save transforms, as it would be written.
+    fn transformed_for_save(&self) -> Rope {
+        let text = if self.config.trim_edited_lines {
+            file_transform::trim_lines_in(&self.text, &self.changes.ranges())
+        } else {
+            self.text.clone()
+        };
+        file_transform::apply_all(&self.config.save_transforms, text, self.config.tab_size)
+    }
+
+    // Whether saving would change the file: cheaply, when neither the text,
+    // the settings, nor the file's size and modification time have changed
+    // since save last wrote it, otherwise by comparing the file's size, and
+    // then its contents, with what save would write.
+    fn modified_on_disk(&self) -> Value {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return ObjectBuilder::new()
+                .insert("ok", false)
+                .insert("error", "no_path")
+                .insert("message", "the buffer has not been opened from or saved to a file")
+                .unwrap(),
+        };
+        let result = |differs: bool, exists: bool, checked: &str| ObjectBuilder::new()
+            .insert("ok", true)
+            .insert("differs", differs)
+            .insert("exists", exists)
+            .insert("checked", checked)
+            .unwrap();
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return result(true, false, "exists");
             }
-            Err(e) => print_err!("create error {}", e),
+            Err(e) => return io_error(&e),
+        };
+        let stamp = metadata.modified().ok().map(|mtime| (metadata.len(), mtime));
+        if !self.is_modified() && stamp.is_some() && stamp == self.disk_stamp {
+            return result(false, true, "stamp");
+        }
+        let text = self.transformed_for_save();
+        let ensure_newline = self.config.ensure_final_newline && text.len() > 0 &&
+            text.byte_at(text.len() - 1) != b'\n';
+        if metadata.len() != (text.len() + ensure_newline as usize) as u64 {
+            return result(true, true, "size");
+        }
+        let mut comparer = match File::open(path) {
+            Ok(file) => ContentComparer { file: file, differs: false },
+            Err(e) => return io_error(&e),
+        };
+        match write_text(&mut comparer, &text, self.config.ensure_final_newline) {
+            Ok(()) => result(comparer.differs, true, "contents"),
+            Err(e) => io_error(&e),
+        }
+    }
+
+    fn save_now(&mut self, path: &str, text: &Rope) -> Value {
+        let result = save_atomically(path, text, self.config.ensure_final_newline, |_| ());
+        let rev = self.head_rev_id();
+        self.finish_save(path, rev, result)
+    }
+
+    /// Records the outcome of writing the text of revision `rev` to `path`,
+    /// returning the result of the save. The buffer is left associated with
+    /// the file unless its text was replaced, by `open` or `revert`, since.
+    pub fn finish_save(&mut self, path: &str, rev: usize, result: io::Result<()>) -> Value {
+        self.saving = false;
+        if let Err(e) = result {
+            return io_error(&e);
+        }
+        if rev >= self.rev_id_base {
+            self.path = Some(path.to_string());
+            self.pristine_rev_id = rev;
+            self.disk_stamp = file_stamp(path);
+            self.read_only = is_read_only(path);
         }
+        ObjectBuilder::new().insert("ok", true).unwrap()
+    }
+
+    // Scrolls so that the cursor's line is at the top, middle or bottom of the
+    // window, leaving the cursor where it is.
+    fn recenter_viewport(&mut self, position: RecenterPosition) {
+        let first_line = self.view.recenter(&self.text, position);
+        self.scroll_top = Some(first_line);
+        self.dirty = true;
     }
 
     fn do_scroll(&mut self, first: i64, last: i64) {
-        self.view.set_scroll(max(first, 0) as usize, last as usize);
+        self.view.set_scroll(&self.text, max(first, 0) as usize, max(last, 0) as usize);
+    }
+
+    // Clamps `offset` to the text and snaps it back to a grapheme boundary.
+    fn snap_offset(&self, offset: usize) -> usize {
+        if offset >= self.text.len() {
+            self.text.len()
+        } else {
+            self.text.prev_grapheme_offset(offset + 1).unwrap()
+        }
+    }
+
+    // Collapses the selection to a caret at its start or end, leaving the
+    // viewport where it is.
+    fn collapse_selection(&mut self, to_start: bool) {
+        let offset = if to_start { self.view.sel_min() } else { self.view.sel_max() };
+        self.this_edit_type = EditType::Select;
+        self.mark = None;
+        self.view.sel_start = offset;
+        self.view.sel_end = offset;
+        self.col = self.view.offset_to_line_col(&self.text, offset).1;
+        self.dirty = true;
+    }
+
+    // Shrinks the selection to exclude whitespace at either end, keeping which
+    // end is active. An all-whitespace selection collapses to its start.
+    fn trim_selection(&mut self) {
+        let (sel_min, sel_max) = (self.view.sel_min(), self.view.sel_max());
+        let selected = self.text.slice_to_string(sel_min, sel_max);
+        let trimmed = selected.trim();
+        let (start, end) = if trimmed.is_empty() {
+            (sel_min, sel_min)
+        } else {
+            let start = sel_min + selected.len() - selected.trim_start().len();
+            (start, start + trimmed.len())
+        };
+        self.this_edit_type = EditType::Select;
+        if self.view.sel_start <= self.view.sel_end {
+            self.set_selection(start, end);
+        } else {
+            self.set_selection(end, start);
+        }
+    }
+
+    // Selects from `start` to `end`; `end` is the active end, brought into view.
+    fn select_range(&mut self, start: usize, end: usize) {
+        let start = self.snap_offset(start);
+        let end = self.snap_offset(end);
+        self.set_selection(start, end);
+    }
+
+    // Grows the selection to the smallest enclosing word, bracket or quote
+    // pair (first the contents, then including the delimiters), line,
+    // paragraph, or the whole document.
+    fn expand_selection(&mut self) {
+        let current = (self.view.sel_start, self.view.sel_end);
+        if self.selection_stack.last() != Some(&current) {
+            self.selection_stack = vec![current];
+        }
+        let (sel_min, sel_max) = (self.view.sel_min(), self.view.sel_max());
+        let mut candidates = vec![(0, self.text.len())];
+        let selected = self.text.slice_to_string(sel_min, sel_max);
+        if selected.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            candidates.push((WordCursor::new(&self.text, sel_min).word_start(),
+                             WordCursor::new(&self.text, sel_max).word_end()));
+        }
+        if let Some((open, close)) = scope::enclosing_pair(&self.text, sel_min, sel_max) {
+            candidates.push((open + 1, close));
+            candidates.push((open, close + 1));
+        }
+        let first_line = self.text.line_of_offset(sel_min);
+        let last_line = self.text.line_of_offset(sel_max);
+        let line_start = self.text.offset_of_line(first_line);
+        let line_end = self.text.offset_of_line(last_line + 1);
+        let mut content_end = line_end;
+        while content_end > line_start &&
+            (self.text.byte_at(content_end - 1) == b'\n' ||
+             self.text.byte_at(content_end - 1) == b'\r') {
+            content_end -= 1;
+        }
+        candidates.push((line_start, content_end));
+        candidates.push((line_start, line_end));
+        if !self.is_blank_line(first_line) && !self.is_blank_line(last_line) {
+            let (first, _) = self.paragraph_lines(first_line);
+            let (_, last) = self.paragraph_lines(last_line);
+            candidates.push((self.text.offset_of_line(first), self.text.offset_of_line(last + 1)));
+        }
+        let best = candidates.into_iter()
+            .filter(|&(start, end)| start <= sel_min && end >= sel_max && end - start > sel_max - sel_min)
+            .min_by_key(|&(start, end)| end - start);
+        if let Some((start, end)) = best {
+            self.select_range(start, end);
+            self.selection_stack.push((self.view.sel_start, self.view.sel_end));
+        }
+    }
+
+    // The offset of the first occurrence of `needle` at or after `from`,
+    // wrapping around to the start of the document.
+    fn find_next(&self, needle: &str, from: usize) -> Option<usize> {
+        find::find_from(&self.text, needle, from).or_else(|| find::find_from(&self.text, needle, 0))
+    }
+
+    // Moves the selection to the next occurrence of the selected text. With no
+    // selection, selects the word around the cursor instead.
+    fn select_next_occurrence(&mut self) {
+        let (sel_min, sel_max) = (self.view.sel_min(), self.view.sel_max());
+        if sel_min == sel_max {
+            let start = WordCursor::new(&self.text, sel_min).word_start();
+            let end = WordCursor::new(&self.text, sel_max).word_end();
+            if start != end {
+                self.select_range(start, end);
+            }
+            return;
+        }
+        let needle = self.text.slice_to_string(sel_min, sel_max);
+        if let Some(start) = self.find_next(&needle, sel_max) {
+            self.select_range(start, start + needle.len());
+        }
+    }
+
+    // Lists the occurrences of the selected text, or of the word around the
+    // cursor with no selection, in order and without overlaps. Until there are
+    // multiple selections, the front-end shows these instead of selecting them.
+    fn select_all_matches(&self) -> Value {
+        let (mut start, mut end) = (self.view.sel_min(), self.view.sel_max());
+        if start == end {
+            start = WordCursor::new(&self.text, start).word_start();
+            end = WordCursor::new(&self.text, end).word_end();
+        }
+        if start == end {
+            return Value::Array(Vec::new());
+        }
+        let needle = self.text.slice_to_string(start, end);
+        let text = self.text.slice_to_string(0, self.text.len());
+        Value::Array(find::find_all(&text, &needle, true, false).into_iter()
+            .map(|(start, end)| ObjectBuilder::new()
+                .insert("start", start)
+                .insert("end", end)
+                .unwrap())
+            .collect())
+    }
+
+    // Moves the cursor to the start of the nearest edited region after the
+    // cursor, or before it if `forward` is false.
+    fn move_to_change(&mut self, forward: bool) {
+        let offset = self.view.sel_end;
+        let target = if forward {
+            self.changes.next_after(offset)
+        } else {
+            self.changes.prev_before(offset)
+        };
+        if let Some(target) = target {
+            self.set_cursor(target, true);
+        }
+    }
+
+    // Moves the cursor to where the text was last edited, if it has been since
+    // it was opened.
+    fn goto_last_edit(&mut self) {
+        if let Some(offset) = self.last_edit_offset {
+            let offset = min(offset, self.text.len());
+            self.set_cursor(offset, true);
+        }
+    }
+
+    // Moves the cursor to just before the next `bracket` after the cursor (one
+    // at the cursor doesn't count, so that repeating moves on), or the previous
+    // one before it if `forward` is false, whether or not it is matched.
+    fn move_to_bracket(&mut self, bracket: char, forward: bool) {
+        let mut cursor = Cursor::new(&self.text, self.view.sel_end);
+        let mut found = None;
+        if forward {
+            cursor.next_codepoint();
+            let mut pos = cursor.pos();
+            while let Some(c) = cursor.next_codepoint() {
+                if c == bracket {
+                    found = Some(pos);
+                    break;
+                }
+                pos = cursor.pos();
+            }
+        } else {
+            while let Some(pos) = cursor.prev::<BaseMetric>() {
+                if cursor.get_leaf().and_then(|(leaf, offset)| leaf[offset..].chars().next())
+                    == Some(bracket) {
+                    found = Some(pos);
+                    break;
+                }
+            }
+        }
+        if let Some(offset) = found {
+            self.set_cursor(offset, true);
+        }
+    }
+
+    // Undoes the last expand_selection step.
+    fn contract_selection(&mut self) {
+        let current = (self.view.sel_start, self.view.sel_end);
+        if self.selection_stack.len() < 2 || self.selection_stack.last() != Some(&current) {
+            return;
+        }
+        self.selection_stack.pop();
+        let (start, end) = *self.selection_stack.last().unwrap();
+        self.select_range(start, end);
     }
 
     fn do_click(&mut self, line: u64, col: u64, flags: u64, _click_count: u64) {
@@ -539,19 +1397,64 @@ impl Editor {
         self.set_cursor(offset, true);
     }
 
+    // Moves the cursor to a column of a logical line, extending the selection
+    // if `modify_selection`.
+    fn move_to_line_col(&mut self, line: usize, col: usize, modify_selection: bool) {
+        let offset = self.view.logical_line_col_to_offset(&self.text, line, col);
+        if modify_selection {
+            self.modify_selection();
+        }
+        self.set_cursor(offset, true);
+    }
+
     fn do_drag(&mut self, line: u64, col: u64, _flags: u64) {
         let offset = self.view.line_col_to_offset(&self.text, line as usize, col as usize);
+        let offset = self.snap_drag(offset);
         self.modify_selection();
         self.set_cursor(offset, true);
     }
 
+    // Moves the dragged end of the selection out to a boundary of the drag
+    // granularity, away from the anchor.
+    fn snap_drag(&self, offset: usize) -> usize {
+        let forward = offset >= self.view.sel_start;
+        match self.drag_granularity {
+            Granularity::Char => offset,
+            Granularity::Word => {
+                let mut cursor = WordCursor::new(&self.text, offset);
+                if forward { cursor.word_end() } else { cursor.word_start() }
+            }
+            Granularity::Line => {
+                let line = self.text.line_of_offset(offset);
+                self.text.offset_of_line(if forward { line + 1 } else { line })
+            }
+        }
+    }
+
     fn do_render_lines(&mut self, first_line: usize, last_line: usize) -> Value {
         self.this_edit_type = self.last_edit_type;  // doesn't break undo group
+        self.view.update_find(&self.text);
         self.view.render_lines(&self.text, first_line as usize, last_line as usize)
     }
 
     fn debug_rewrap(&mut self) {
-        self.view.rewrap(&self.text, 72);
+        self.do_set_wrap_mode(WrapMode::Column(72));
+    }
+
+    fn do_set_wrap_mode(&mut self, wrap_mode: WrapMode) {
+        self.view.set_wrap_mode(&self.text, wrap_mode);
+        self.view.scroll_to_cursor(&self.text);
+        self.dirty = true;
+    }
+
+    fn do_set_window_width(&mut self, cols: usize) {
+        self.view.set_window_width(&self.text, cols);
+        self.view.scroll_to_cursor(&self.text);
+        self.dirty = true;
+    }
+
+    fn do_set_hanging_indent(&mut self, indent: usize) {
+        self.view.set_hanging_indent(&self.text, indent);
         self.dirty = true;
     }
 
@@ -588,19 +1491,42 @@ impl Editor {
         }
     }
 
-    fn do_undo(&mut self) {
+    // Returns whether there was anything to undo.
+    fn do_undo(&mut self) -> bool {
         if self.cur_undo > 0 {
             self.cur_undo -= 1;
-            debug_assert!(self.undos.insert(self.live_undos[self.cur_undo]));
+            let group = self.live_undos[self.cur_undo];
+            debug_assert!(self.undos.insert(group));
             self.update_undos();
+            let before = self.undo_selections.get(&group).map(|selections| selections.before);
+            self.restore_selection(before);
+            true
+        } else {
+            false
         }
     }
 
-    fn do_redo(&mut self) {
+    // Returns whether there was anything to redo.
+    fn do_redo(&mut self) -> bool {
         if self.cur_undo < self.live_undos.len() {
-            debug_assert!(self.undos.remove(&self.live_undos[self.cur_undo]));
+            let group = self.live_undos[self.cur_undo];
+            debug_assert!(self.undos.remove(&group));
             self.cur_undo += 1;
             self.update_undos();
+            let after = self.undo_selections.get(&group).and_then(|selections| selections.after);
+            self.restore_selection(after);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Sets the selection recorded for an undo group, if any, as far as it
+    // still fits the text.
+    fn restore_selection(&mut self, selection: Option<(usize, usize)>) {
+        if let Some((start, end)) = selection {
+            let (start, end) = (self.snap_offset(start), self.snap_offset(end));
+            self.set_selection(start, end);
         }
     }
 
@@ -624,6 +1550,37 @@ impl Editor {
         self.add_delta(interval, Rope::from(swapped), end, end);
     }
 
+    // Swaps the cursor's line with the one above it (or, on the first line,
+    // with the one below), leaving the cursor after both, as in Emacs. Line
+    // endings stay where they were, so a last line without one keeps lacking it.
+    // The empty line after a final newline doesn't count: on it, the two lines
+    // above are swapped.
+    fn transpose_lines(&mut self) {
+        let mut last_line = self.text.line_of_offset(self.text.len());
+        if last_line > 0 && self.text.offset_of_line(last_line) == self.text.len() {
+            last_line -= 1;
+        }
+        let line = min(self.text.line_of_offset(self.view.sel_end), last_line);
+        let first = if line == 0 { 0 } else { line - 1 };
+        if first + 1 > last_line {
+            return;
+        }
+        let start = self.text.offset_of_line(first);
+        let middle = self.text.offset_of_line(first + 1);
+        let end = if first + 2 > last_line {
+            self.text.len()
+        } else {
+            self.text.offset_of_line(first + 2)
+        };
+        let upper = self.text.slice_to_string(start, middle);
+        let lower = self.text.slice_to_string(middle, end);
+        let (upper, upper_ending) = split_line_ending(&upper);
+        let (lower, lower_ending) = split_line_ending(&lower);
+        let swapped = format!("{}{}{}{}", lower, upper_ending, upper, lower_ending);
+        let iv = Interval::new_closed_open(start, end);
+        self.add_delta(iv, Rope::from(swapped), end, end);
+    }
+
     fn delete_to_end_of_paragraph(&mut self, tab_ctx: &TabCtx) {
         let current = self.view.sel_max();
         let offset = self.cursor_end_offset();
@@ -644,18 +1601,1011 @@ impl Editor {
         tab_ctx.set_kill_ring(Rope::from(val));
     }
 
-    fn yank(&mut self, tab_ctx: &TabCtx) {
-        self.insert(&*String::from(tab_ctx.get_kill_ring()));
+    fn do_toggle_block_comment(&mut self, delimiters: Option<(&str, &str)>) {
+        let language = self.config.language.as_deref();
+        match delimiters.or_else(|| language.and_then(config::block_comment_delimiters)) {
+            Some((open, close)) => self.toggle_block_comment(open, close),
+            None => print_err!("no block comment delimiters for language {:?}", language),
+        }
     }
 
-    pub fn do_rpc(&mut self,
-                  cmd: EditCommand,
-                  tab_ctx: TabCtx)
-                  -> Option<Value> {
+    // Wraps the selection in the given block comment delimiters, or removes them
+    // if the selection, or its immediate surroundings, are already wrapped.
+    fn toggle_block_comment(&mut self, open: &str, close: &str) {
+        let min = self.view.sel_min();
+        let max = self.view.sel_max();
+        let mut builder = DeltaBuilder::new(self.text.len());
+        let (start, end);
+        if max - min >= open.len() + close.len() &&
+            self.text.slice_to_string(min, min + open.len()) == open &&
+            self.text.slice_to_string(max - close.len(), max) == close {
+
+            builder.delete(Interval::new_closed_open(min, min + open.len()));
+            builder.delete(Interval::new_closed_open(max - close.len(), max));
+            start = min;
+            end = max - open.len() - close.len();
+        } else if min >= open.len() && max + close.len() <= self.text.len() &&
+            self.text.slice_to_string(min - open.len(), min) == open &&
+            self.text.slice_to_string(max, max + close.len()) == close {
+
+            builder.delete(Interval::new_closed_open(min - open.len(), min));
+            builder.delete(Interval::new_closed_open(max, max + close.len()));
+            start = min - open.len();
+            end = max - open.len();
+        } else {
+            builder.replace(Interval::new_closed_open(min, min), Rope::from(open));
+            builder.replace(Interval::new_closed_open(max, max), Rope::from(close));
+            start = min + open.len();
+            end = max + open.len();
+        }
+        self.add_full_delta(builder.build(), start, end);
+    }
 
-        use rpc::EditCommand::*;
+    fn do_toggle_line_comment(&mut self, prefix: Option<&str>) {
+        let language = self.config.language.as_deref();
+        match prefix.or_else(|| language.and_then(config::line_comment_prefix)) {
+            Some(prefix) => self.toggle_line_comment(prefix),
+            None => print_err!("no line comment prefix for language {:?}", language),
+        }
+    }
+
+    // Comments out the lines touched by the selection, or the cursor's line, by
+    // inserting `prefix` and a space at their least indentation, or uncomments
+    // them if every non-blank one already starts with `prefix`. Blank lines are
+    // left alone.
+    fn toggle_line_comment(&mut self, prefix: &str) {
+        let (first, last) = if self.view.sel_start == self.view.sel_end {
+            let line = self.text.line_of_offset(self.view.sel_end);
+            (line, line + 1)
+        } else {
+            self.selected_lines()
+        };
+        let lines = (first..last).filter_map(|line| {
+            let start = self.text.offset_of_line(line);
+            let s = self.text.slice_to_string(start, self.text.offset_of_line(line + 1));
+            let indent = s.len() - s.trim_start_matches(&[' ', '\t'][..]).len();
+            if s.trim().is_empty() { None } else { Some((start, indent, s)) }
+        }).collect::<Vec<_>>();
+        let uncomment = lines.iter().all(|&(_, indent, ref s)| s[indent..].starts_with(prefix));
+        // the edits as (offset, bytes deleted, text inserted)
+        let edits = if uncomment {
+            lines.iter().map(|&(start, indent, ref s)| {
+                let rest = &s[indent + prefix.len()..];
+                let len = if rest.starts_with(' ') { prefix.len() + 1 } else { prefix.len() };
+                (start + indent, len, String::new())
+            }).collect::<Vec<_>>()
+        } else {
+            let column = lines.iter().map(|&(_, indent, _)| indent).min().unwrap_or(0);
+            lines.iter().map(|&(start, _, _)| (start + column, 0, format!("{} ", prefix)))
+                .collect()
+        };
+        if edits.is_empty() {
+            return;
+        }
+        let mut builder = DeltaBuilder::new(self.text.len());
+        for &(offset, len, ref text) in &edits {
+            builder.replace(Interval::new_closed_open(offset, offset + len), Rope::from(text));
+        }
+        let new_pos = |pos: usize| edits.iter().filter(|&&(offset, _, _)| offset < pos)
+            .fold(pos, |new, &(offset, len, ref text)| new + text.len() - min(len, pos - offset));
+        let (start, end) = (new_pos(self.view.sel_start), new_pos(self.view.sel_end));
+        self.add_full_delta(builder.build(), start, end);
+    }
+
+    // Removes the innermost bracket or quote pair enclosing the selection,
+    // found as expand_selection finds it, keeping the text between them.
+    fn delete_surround(&mut self) {
+        let (sel_min, sel_max) = (self.view.sel_min(), self.view.sel_max());
+        if let Some((open, close)) = scope::enclosing_pair(&self.text, sel_min, sel_max) {
+            let mut builder = DeltaBuilder::new(self.text.len());
+            builder.delete(Interval::new_closed_open(open, open + 1));
+            builder.delete(Interval::new_closed_open(close, close + 1));
+            let new_pos = |offset: usize| min(max(offset, open + 1), close) - 1;
+            let (start, end) = (new_pos(self.view.sel_start), new_pos(self.view.sel_end));
+            self.add_full_delta(builder.build(), start, end);
+        }
+    }
+
+    // Records `edit` as the last edit. An insert that went into the same undo
+    // group as the insert before it, as consecutive typing does, extends it.
+    fn record_edit(&mut self, edit: RepeatableEdit) {
+        let continues = self.command_undo_group.is_some() &&
+            self.command_undo_group == self.last_edit_group;
+        match (&mut self.last_edit, edit) {
+            (&mut Some(RepeatableEdit::Insert(ref mut text)), RepeatableEdit::Insert(ref chars))
+                if continues => text.push_str(chars),
+            (last_edit, edit) => *last_edit = Some(edit),
+        }
+        self.last_edit_group = self.command_undo_group;
+    }
+
+    // Applies the last edit again at the cursor, as its own undo group.
+    fn repeat_last_edit(&mut self) {
+        let edit = match self.last_edit.clone() {
+            Some(edit) => edit,
+            None => return,
+        };
+        // so that it doesn't join the undo group of the edit before
+        self.last_edit_type = EditType::Other;
+        match edit {
+            RepeatableEdit::Insert(text) => self.do_insert(&text),
+            RepeatableEdit::InsertNewline => self.insert_newline(),
+            RepeatableEdit::InsertTab => self.insert_tab(),
+            RepeatableEdit::DeleteForward => self.delete_forward(),
+            RepeatableEdit::DeleteBackward => self.delete_backward(),
+            RepeatableEdit::DeleteToBeginningOfLine => self.delete_to_beginning_of_line(),
+            RepeatableEdit::Delete(motion) => self.do_delete(motion),
+        }
+    }
+
+    // The text of a logical line, including its line ending.
+    fn line_string(&self, line_num: usize) -> String {
+        let start_offset = self.text.offset_of_line(line_num);
+        let end_offset = self.text.offset_of_line(line_num + 1);
+        self.text.slice_to_string(start_offset, end_offset)
+    }
+
+    // Inserts `count` copies of the selection after it, or of the cursor's line
+    // below it if nothing is selected, leaving the cursor at the end of the last
+    // copy. The copies are cut short at MAX_DUPLICATE_BYTES in all.
+    fn duplicate_selection(&mut self, count: usize) {
+        let (sel_min, sel_max) = (self.view.sel_min(), self.view.sel_max());
+        let (offset, unit, ending_len) = if sel_min == sel_max {
+            let line = self.text.line_of_offset(sel_max);
+            let line_string = self.line_string(line);
+            let offset = self.text.offset_of_line(line) + line_string.len();
+            let (content, ending) = split_line_ending(&line_string);
+            if ending.is_empty() {
+                (offset, format!("\n{}", content), 0)
+            } else {
+                (offset, line_string.clone(), ending.len())
+            }
+        } else {
+            (sel_max, self.text.slice_to_string(sel_min, sel_max), 0)
+        };
+        let count = min(count, MAX_DUPLICATE_BYTES / unit.len());
+        if count == 0 {
+            return;
+        }
+        let copies = unit.repeat(count);
+        let cursor = offset + copies.len() - ending_len;
+        self.add_delta(Interval::new_closed_open(offset, offset), Rope::from(copies), cursor, cursor);
+    }
+
+    // Opens a new line below (or above) the cursor's line, with the same
+    // indentation, and moves the cursor onto it, as Vim's `o` and `O` do.
+    fn open_line(&mut self, below: bool) {
+        let line = self.text.line_of_offset(self.view.sel_end);
+        let line_start = self.text.offset_of_line(line);
+        let line_string = self.line_string(line);
+        let (content, ending) = split_line_ending(&line_string);
+        let ending = if ending.is_empty() { "\n" } else { ending };
+        let indent: String = content.chars().take_while(|&c| c == ' ' || c == '\t').collect();
+        let (offset, new, cursor) = if below {
+            let offset = line_start + content.len();
+            (offset, format!("{}{}", ending, indent), offset + ending.len() + indent.len())
+        } else {
+            (line_start, format!("{}{}", indent, ending), line_start + indent.len())
+        };
+        let iv = Interval::new_closed_open(offset, offset);
+        self.add_delta(iv, Rope::from(new), cursor, cursor);
+    }
+
+    // The display width of a line's indentation, or None if it is blank.
+    fn indent_width(&self, line: usize) -> Option<usize> {
+        let line_string = self.line_string(line);
+        let content = line_string.trim_start_matches(&[' ', '\t'][..]);
+        if content.trim().is_empty() {
+            None
+        } else {
+            let indent = &line_string[..line_string.len() - content.len()];
+            Some(display_width(indent, 0, self.config.tab_size))
+        }
+    }
+
+    // Moves to the first non-blank character of the next (or previous) line
+    // indented as much as the cursor's line, skipping blank lines and more
+    // deeply indented ones. Doesn't move if a less indented line comes first.
+    fn move_to_sibling(&mut self, forward: bool) {
+        let line = self.text.line_of_offset(self.view.sel_end);
+        let last_line = self.text.line_of_offset(self.text.len());
+        let width = match self.indent_width(line) {
+            Some(width) => width,
+            None => return,
+        };
+        let mut target = line;
+        loop {
+            if forward && target < last_line {
+                target += 1;
+            } else if !forward && target > 0 {
+                target -= 1;
+            } else {
+                return;
+            }
+            match self.indent_width(target) {
+                Some(w) if w == width => break,
+                Some(w) if w < width => return,
+                _ => (),
+            }
+        }
+        let offset = self.first_non_blank(self.text.offset_of_line(target));
+        self.set_cursor(offset, true);
+    }
+
+    // Moves to the start of the first (or last) line of the block around the
+    // cursor's line: the lines indented at least as much, with blank lines in
+    // between.
+    fn move_to_block_edge(&mut self, forward: bool) {
+        let line = self.text.line_of_offset(self.view.sel_end);
+        let last_line = self.text.line_of_offset(self.text.len());
+        let width = match self.indent_width(line) {
+            Some(width) => width,
+            None => return,
+        };
+        let mut target = line;
+        let mut probe = line;
+        loop {
+            if forward && probe < last_line {
+                probe += 1;
+            } else if !forward && probe > 0 {
+                probe -= 1;
+            } else {
+                break;
+            }
+            match self.indent_width(probe) {
+                Some(w) if w >= width => target = probe,
+                Some(_) => break,
+                None => (),
+            }
+        }
+        let offset = self.text.offset_of_line(target);
+        self.set_cursor(offset, true);
+    }
+
+    fn is_blank_line(&self, line_num: usize) -> bool {
+        self.line_string(line_num).trim().is_empty()
+    }
+
+    // The first and last lines of the run of non-blank lines around `line`.
+    fn paragraph_lines(&self, line: usize) -> (usize, usize) {
+        let n_lines = self.text.measure::<LinesMetric>() + 1;
+        let mut first = line;
+        while first > 0 && !self.is_blank_line(first - 1) {
+            first -= 1;
+        }
+        let mut last = line;
+        while last + 1 < n_lines && !self.is_blank_line(last + 1) {
+            last += 1;
+        }
+        (first, last)
+    }
+
+    // Hard-wraps the paragraph (run of non-blank lines) containing the cursor.
+    fn reflow_paragraph(&mut self, width: usize) {
+        let cursor_line = self.text.line_of_offset(self.view.sel_end);
+        if self.is_blank_line(cursor_line) {
+            return;
+        }
+        let (first, last) = self.paragraph_lines(cursor_line);
+        let start = self.text.offset_of_line(first);
+        let mut end = self.text.offset_of_line(last + 1);
+        // the paragraph keeps its final line ending
+        if end > start && self.text.byte_at(end - 1) == b'\n' {
+            end -= 1;
+        }
+        let old = self.text.slice_to_string(start, end);
+        let new = transform::reflow(&old, width);
+        if new == old {
+            return;
+        }
+
+        // keep the cursor after the same number of non-blank characters
+        let cursor = min(self.view.sel_end, end) - start;
+        let n_chars = old[..cursor].chars().filter(|c| !c.is_whitespace()).count();
+        let mut new_cursor = new.len();
+        let mut seen = 0;
+        for (i, c) in new.char_indices() {
+            if seen == n_chars {
+                new_cursor = i;
+                break;
+            }
+            if !c.is_whitespace() {
+                seen += 1;
+            }
+        }
+        let new_cursor = start + new_cursor;
+        let iv = Interval::new_closed_open(start, end);
+        self.add_delta(iv, Rope::from(new), new_cursor, new_cursor);
+    }
+
+    // The logical lines touched by the selection, as a half-open range; the
+    // whole document when nothing is selected.
+    fn selected_lines(&self) -> (usize, usize) {
+        if self.view.sel_start == self.view.sel_end {
+            return (0, self.text.measure::<LinesMetric>() + 1);
+        }
+        let first = self.text.line_of_offset(self.view.sel_min());
+        let last = self.text.line_of_offset(self.view.sel_max());
+        // a selection ending at the start of a line doesn't include it
+        if last > first && self.text.offset_of_line(last) == self.view.sel_max() {
+            (first, last)
+        } else {
+            (first, last + 1)
+        }
+    }
+
+    // Grows the selection to whole lines, including the line ending of the last
+    // line, so that it ends at the start of the next line. The direction of the
+    // selection is kept.
+    fn expand_to_lines(&mut self) {
+        let (first, last) = if self.view.sel_start == self.view.sel_end {
+            let line = self.text.line_of_offset(self.view.sel_end);
+            (line, line + 1)
+        } else {
+            self.selected_lines()
+        };
+        let start = self.text.offset_of_line(first);
+        let end = self.text.offset_of_line(last);
+        self.modify_selection();
+        if self.view.sel_end < self.view.sel_start {
+            self.set_selection(end, start);
+        } else {
+            self.set_selection(start, end);
+        }
+    }
+
+    // Rewrites each line in `first..last` with `f`, as a single edit. A selection
+    // is extended to cover the rewritten lines; a caret stays on its line.
+    fn transform_lines<F: FnMut(&str) -> String>(&mut self, first: usize, last: usize, mut f: F) {
+        let mut builder = DeltaBuilder::new(self.text.len());
+        let caret_line = self.text.line_of_offset(self.view.sel_end);
+        let caret_col = self.view.sel_end - self.text.offset_of_line(caret_line);
+        let mut new_caret = self.view.sel_end;
+        let mut new_len = self.text.len();
+        for line in first..last {
+            let start = self.text.offset_of_line(line);
+            let end = self.text.offset_of_line(line + 1);
+            let old = self.text.slice_to_string(start, end);
+            let new = f(&old);
+            if line == caret_line {
+                let ending = new.bytes().rev().take_while(|&b| b == b'\n' || b == b'\r').count();
+                let content_len = new.len() - ending;
+                let new_start = start + new_len - self.text.len();
+                new_caret = new_start + min(caret_col, content_len);
+                while !new.is_char_boundary(new_caret - new_start) {
+                    new_caret -= 1;
+                }
+            }
+            if new != old {
+                new_len = new_len + new.len() - old.len();
+                builder.replace(Interval::new_closed_open(start, end), Rope::from(new));
+            }
+        }
+        if builder.is_empty() {
+            return;
+        }
+        let (start, end) = if self.view.sel_start == self.view.sel_end {
+            (new_caret, new_caret)
+        } else {
+            let end = self.text.offset_of_line(last) + new_len - self.text.len();
+            if self.view.sel_start < self.view.sel_end {
+                (self.text.offset_of_line(first), end)
+            } else {
+                (end, self.text.offset_of_line(first))
+            }
+        };
+        self.add_full_delta(builder.build(), start, end);
+    }
+
+    // Deletes the blank lines around the cursor, except one.
+    fn delete_blank_lines(&mut self) {
+        let line = self.text.line_of_offset(self.view.sel_end);
+        if !self.is_blank_line(line) {
+            return;
+        }
+        let n_lines = self.text.measure::<LinesMetric>() + 1;
+        let mut first = line;
+        while first > 0 && self.is_blank_line(first - 1) {
+            first -= 1;
+        }
+        let mut last = line;
+        while last + 1 < n_lines && self.is_blank_line(last + 1) {
+            last += 1;
+        }
+        let start = self.text.offset_of_line(first + 1);
+        let end = self.text.offset_of_line(last + 1);
+        if start < end {
+            let cursor = self.text.offset_of_line(first);
+            self.add_delta(Interval::new_closed_open(start, end), Rope::from(""), cursor, cursor);
+        }
+    }
+
+    fn delete_all_blank_lines(&mut self) {
+        let (first, last) = self.selected_lines();
+        self.transform_lines(first, last, |line| {
+            if line.trim().is_empty() { String::new() } else { line.to_string() }
+        });
+    }
+
+    // Removes the indentation common to the non-blank lines touched by the
+    // selection, or the whole document, as one edit; blank lines are emptied.
+    fn dedent_to_common(&mut self) {
+        let (first, last) = self.selected_lines();
+        let tab_size = self.config.tab_size;
+        if let Some(width) = (first..last).filter_map(|line| self.indent_width(line)).min() {
+            self.transform_lines(first, last,
+                |line| transform::remove_indent(line, width, tab_size));
+        }
+    }
+
+    // Pads the lines touched by the selection, or the whole document, with
+    // spaces so that the first `delimiter` of each starts in the same column,
+    // as one edit. Lines without it are left alone.
+    fn align_on(&mut self, delimiter: &str) {
+        let (first, last) = self.selected_lines();
+        let tab_size = self.config.tab_size;
+        let column = (first..last).filter_map(|line| {
+            let s = self.text.slice_to_string(self.text.offset_of_line(line),
+                                              self.text.offset_of_line(line + 1));
+            s.find(delimiter).map(|i| display_width(&s[..i], 0, tab_size))
+        }).max();
+        if let Some(column) = column {
+            self.transform_lines(first, last, |line| match line.find(delimiter) {
+                Some(i) => {
+                    let padding = column - display_width(&line[..i], 0, tab_size);
+                    format!("{}{}{}", &line[..i], " ".repeat(padding), &line[i..])
+                }
+                None => line.to_string(),
+            });
+        }
+    }
+
+    fn tabs_to_spaces(&mut self, tab_size: usize, leading_only: bool) {
+        let (first, last) = self.selected_lines();
+        self.transform_lines(first, last,
+            |line| transform::expand_tabs(line, tab_size, leading_only));
+    }
+
+    fn spaces_to_tabs(&mut self, tab_size: usize, leading_only: bool) {
+        let (first, last) = self.selected_lines();
+        self.transform_lines(first, last,
+            |line| transform::unexpand_spaces(line, tab_size, leading_only));
+    }
+
+    // Puts a reference to the cursor's position in the file onto the kill
+    // ring, with 1-based line and column (in characters) numbers.
+    fn copy_location(&self, style: LocationStyle, tab_ctx: &TabCtx) -> Value {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return ObjectBuilder::new()
+                .insert("ok", false)
+                .insert("error", "no_path")
+                .insert("message", "the buffer has not been opened from or saved to a file")
+                .unwrap(),
+        };
+        let offset = self.view.sel_end;
+        let line = self.text.line_of_offset(offset);
+        let line_start = self.text.offset_of_line(line);
+        let col = self.text.slice_to_string(line_start, offset).chars().count();
+        let location = match style {
+            LocationStyle::Line => format!("{}:{}", path, line + 1),
+            LocationStyle::LineCol => format!("{}:{}:{}", path, line + 1, col + 1),
+            LocationStyle::HashLine => format!("{}#L{}", path, line + 1),
+        };
+        tab_ctx.set_kill_ring(Rope::from(&location[..]));
+        ObjectBuilder::new()
+            .insert("ok", true)
+            .insert("location", location)
+            .unwrap()
+    }
+
+    // The numbers of characters (graphemes), words and lines in the selection.
+    // A final line ending doesn't count as the start of another line.
+    fn selection_stats(&self) -> Value {
+        let (start, end) = (self.view.sel_min(), self.view.sel_max());
+        let mut chars = 0;
+        let mut offset = start;
+        while offset < end {
+            offset = self.text.next_grapheme_offset(offset).unwrap_or(end);
+            chars += 1;
+        }
+        let s = self.text.slice_to_string(start, end);
+        let lines = if s.is_empty() {
+            0
+        } else {
+            s.matches('\n').count() + if s.ends_with('\n') { 0 } else { 1 }
+        };
+        ObjectBuilder::new()
+            .insert("chars", chars)
+            .insert("words", count_words(&s))
+            .insert("lines", lines)
+            .unwrap()
+    }
+
+    // Selects the rectangle between two logical line and column positions, as
+    // a range on each line; lines too short to reach a column are selected
+    // to their ends.
+    fn set_block_selection(&mut self, start_line: usize, start_col: usize, end_line: usize,
+                           end_col: usize) {
+        let (first_col, last_col) = (min(start_col, end_col), max(start_col, end_col));
+        let segments = (min(start_line, end_line)..max(start_line, end_line) + 1)
+            .map(|line| (self.view.logical_line_col_to_offset(&self.text, line, first_col),
+                         self.view.logical_line_col_to_offset(&self.text, line, last_col)))
+            .collect();
+        self.view.set_block(segments);
+        self.dirty = true;
+    }
+
+    fn clear_block_selection(&mut self) {
+        self.view.set_block(Vec::new());
+        self.dirty = true;
+    }
+
+    // The segments of the block selection, with logical lines and columns.
+    fn block_selection(&self) -> Value {
+        Value::Array(self.view.block().iter().map(|&(start, end)| {
+            let (line, start_col) = self.view.offset_to_logical_line_col(&self.text, start);
+            ObjectBuilder::new()
+                .insert("line", line)
+                .insert("start_col", start_col)
+                .insert("end_col", start_col + end - start)
+                .unwrap()
+        }).collect())
+    }
+
+    // Swaps the text of the two ranges of a block selection over two lines,
+    // the only case of several selections so far, selecting the text moved to
+    // the second. Does nothing with any other number of ranges.
+    fn transpose_selections(&mut self) {
+        let ((a_start, a_end), (b_start, b_end)) = match *self.view.block() {
+            [a, b] => (a, b),
+            _ => return,
+        };
+        let a_text = Rope::from(self.text.slice_to_string(a_start, a_end));
+        let b_text = Rope::from(self.text.slice_to_string(b_start, b_end));
+        let mut builder = DeltaBuilder::new(self.text.len());
+        builder.replace(Interval::new_closed_open(a_start, a_end), b_text);
+        builder.replace(Interval::new_closed_open(b_start, b_end), a_text);
+        // the first segment's text now ends where the second one did
+        let end = b_end;
+        let start = end - (a_end - a_start);
+        self.add_full_delta(builder.build(), start, end);
+    }
+
+    // Folds the lines after `start_line` up to `end_line` (logical lines) behind
+    // it, returning the placeholder's text and the number of lines hidden.
+    fn summarize_region(&mut self, start_line: usize, end_line: usize) -> Value {
+        let last_line = self.text.line_of_offset(self.text.len());
+        let start_line = min(start_line, last_line);
+        let end_line = min(max(end_line, start_line), last_line);
+        let start = self.text.offset_of_line(start_line);
+        let hidden_start = self.text.offset_of_line(start_line + 1);
+        let hidden_end = self.text.offset_of_line(end_line + 1);
+        self.view.add_fold(hidden_start, hidden_end);
+        if self.view.sel_end >= hidden_start && self.view.sel_end < hidden_end {
+            self.set_cursor(start, true);
+        }
+        self.dirty = true;
+        let first_line = self.text.slice_to_string(start, hidden_start);
+        ObjectBuilder::new()
+            .insert("text", split_line_ending(&first_line).0)
+            .insert("hidden_lines", count_lines(&self.text, hidden_start, hidden_end))
+            .unwrap()
+    }
+
+    // Unfolds the fold that `line` is the placeholder of or is hidden in.
+    fn unfold_region(&mut self, line: usize) -> bool {
+        let line_start = self.text.offset_of_line(line);
+        let line_end = self.text.offset_of_line(line + 1);
+        let unfolded = self.view.remove_fold(line_start, line_end);
+        self.dirty = self.dirty || unfolded;
+        unfolded
+    }
+
+    // The logical line and byte column of each offset, in the order given.
+    fn offsets_to_line_cols(&self, offsets: &[usize]) -> Value {
+        Value::Array(line_cols(&self.text, offsets).into_iter().map(|(line, col)| {
+            ObjectBuilder::new()
+                .insert("line", line)
+                .insert("col", col)
+                .unwrap()
+        }).collect())
+    }
+
+    // The display width of a logical line, without its line ending, or null if
+    // there is no such line.
+    fn measure_line(&self, line: usize) -> Value {
+        if line > self.text.line_of_offset(self.text.len()) {
+            return Value::Null;
+        }
+        let line = self.line_string(line);
+        let width = display_width(split_line_ending(&line).0, 0, self.config.tab_size);
+        ObjectBuilder::new().insert("width", width).unwrap()
+    }
+
+    // The boundaries the cursor moves between around `offset`, which is
+    // clamped to the text and snapped back to a boundary first.
+    fn boundaries_near(&self, offset: usize) -> Value {
+        let offset = self.snap_offset(offset);
+        ObjectBuilder::new()
+            .insert("offset", offset)
+            .insert("prev_grapheme", self.text.prev_grapheme_offset(offset))
+            .insert("next_grapheme", self.text.next_grapheme_offset(offset))
+            .insert("prev_codepoint", self.text.prev_codepoint_offset(offset))
+            .insert("next_codepoint", self.text.next_codepoint_offset(offset))
+            .unwrap()
+    }
+
+    // Replaces the selection, or the whole document if nothing is selected,
+    // with the output of `command` run on it, as one edit. Nothing changes if
+    // filters aren't allowed or the command fails.
+    fn filter_selection(&mut self, command: &str) -> Value {
+        if !self.config.allow_filter_commands {
+            return ObjectBuilder::new()
+                .insert("ok", false)
+                .insert("error", "disabled")
+                .insert("message", "filter commands are disabled by allow_filter_commands")
+                .unwrap();
+        }
+        let (start, end) = if self.view.sel_start == self.view.sel_end {
+            (0, self.text.len())
+        } else {
+            (self.view.sel_min(), self.view.sel_max())
+        };
+        let output = match run_filter(command, self.text.slice_to_string(start, end)) {
+            Ok(output) => output,
+            Err(e) => return e,
+        };
+        let new_end = start + output.len();
+        self.add_delta(Interval::new_closed_open(start, end), Rope::from(output), start, new_end);
+        ObjectBuilder::new().insert("ok", true).unwrap()
+    }
+
+    // Moves the cursor to the start of the first line after the cursor's that
+    // is wider than `limit` columns, returning the line and its width, or null
+    // (leaving the cursor alone) if there is none.
+    fn next_long_line(&mut self, limit: usize) -> Value {
+        let last_line = self.text.line_of_offset(self.text.len());
+        let first = self.text.line_of_offset(self.view.sel_end) + 1;
+        for line in first..last_line + 1 {
+            let line_string = self.line_string(line);
+            let width = display_width(split_line_ending(&line_string).0, 0, self.config.tab_size);
+            if width > limit {
+                let offset = self.text.offset_of_line(line);
+                self.set_cursor(offset, true);
+                return ObjectBuilder::new()
+                    .insert("line", line)
+                    .insert("width", width)
+                    .unwrap();
+            }
+        }
+        Value::Null
+    }
+
+    fn visible_range(&self) -> Value {
+        let (start, end) = self.view.visible_range(&self.text);
+        ObjectBuilder::new()
+            .insert("start", start)
+            .insert("end", end)
+            .unwrap()
+    }
+
+    // The text of the cursor's line, without its line ending, and the cursor's
+    // column in it, in bytes.
+    fn current_line(&self) -> Value {
+        // the view's lines are visual lines when wrapping, so use the text's
+        let line = self.text.line_of_offset(self.view.sel_end);
+        let start = self.text.offset_of_line(line);
+        let end = self.text.offset_of_line(line + 1);
+        let col = self.view.sel_end - start;
+        let line_text = self.text.slice_to_string(start, end);
+        let content = split_line_ending(&line_text).0;
+        ObjectBuilder::new()
+            .insert("line", line)
+            .insert("text", content)
+            .insert("col", min(col, content.len()))
+            .unwrap()
+    }
+
+    // The line ending style of the text, `"lf"`, `"crlf"`, `"mixed"` or
+    // `"none"`, and the numbers of LF and CRLF line endings.
+    fn line_endings(&self) -> (&'static str, usize, usize) {
+        let mut lf = 0;
+        let mut crlf = 0;
+        for line in self.text.lines_raw(0, self.text.len()) {
+            if line.ends_with("\r\n") {
+                crlf += 1;
+            } else if line.ends_with('\n') {
+                lf += 1;
+            }
+        }
+        let kind = match (lf, crlf) {
+            (0, 0) => "none",
+            (_, 0) => "lf",
+            (0, _) => "crlf",
+            _ => "mixed",
+        };
+        (kind, lf, crlf)
+    }
+
+    fn line_ending_info(&self) -> Value {
+        let (kind, lf, crlf) = self.line_endings();
+        ObjectBuilder::new()
+            .insert("kind", kind)
+            .insert("lf", lf)
+            .insert("crlf", crlf)
+            .unwrap()
+    }
+
+    // The settings in effect, with defaults applied.
+    fn view_config(&self) -> Value {
+        let wrap_mode = match self.view.wrap_mode() {
+            WrapMode::NoWrap => "none",
+            WrapMode::Window => "window",
+            WrapMode::Column(_) => "column",
+        };
+        let wrap_width = self.view.wrap_cols().map_or(Value::Null, |cols| Value::U64(cols as u64));
+        ObjectBuilder::new()
+            .insert("wrap_mode", wrap_mode)
+            .insert("wrap_width", wrap_width)
+            .insert("tab_size", self.config.tab_size)
+            .insert("line_ending", self.line_endings().0)
+            .insert("ensure_final_newline", self.config.ensure_final_newline)
+            .unwrap()
+    }
+
+    fn normalize_line_endings(&mut self, to: LineEnding) {
+        let mut builder = DeltaBuilder::new(self.text.len());
+        // number of edits before each end of the selection
+        let mut n_before_start = 0;
+        let mut n_before_end = 0;
+        let mut offset = 0;
+        for line in self.text.lines_raw(0, self.text.len()) {
+            offset += line.len();
+            let edit_pos = match to {
+                LineEnding::Crlf if line.ends_with('\n') && !line.ends_with("\r\n") => {
+                    builder.replace(Interval::new_closed_open(offset - 1, offset - 1),
+                        Rope::from("\r"));
+                    offset - 1
+                }
+                LineEnding::Lf if line.ends_with("\r\n") => {
+                    builder.delete(Interval::new_closed_open(offset - 2, offset - 1));
+                    offset - 2
+                }
+                _ => continue,
+            };
+            if self.view.sel_start > edit_pos {
+                n_before_start += 1;
+            }
+            if self.view.sel_end > edit_pos {
+                n_before_end += 1;
+            }
+        }
+        if builder.is_empty() {
+            return;
+        }
+        let (start, end) = match to {
+            LineEnding::Crlf => (self.view.sel_start + n_before_start, self.view.sel_end + n_before_end),
+            LineEnding::Lf => (self.view.sel_start - n_before_start, self.view.sel_end - n_before_end),
+        };
+        self.add_full_delta(builder.build(), start, end);
+    }
+
+    pub fn set_config(&mut self, settings: Vec<Setting>) {
+        for setting in settings {
+            self.config.apply(setting);
+        }
+        // the settings may change what save writes
+        self.disk_stamp = None;
+        self.view.set_show_trailing_whitespace(self.config.show_trailing_whitespace);
+        self.dirty = true;
+    }
+
+    // The bytes of the selected text, with all but printable ASCII as `\x`
+    // escapes.
+    fn selection_bytes(&self) -> Value {
+        let s = self.text.slice_to_string(self.view.sel_min(), self.view.sel_max());
+        Value::String(transform::escape_bytes(&s))
+    }
+
+    // The edits `cmd` would make to the text, computed on a copy of the editor
+    // so that neither the text nor the undo history changes. Only commands
+    // that just transform the text can be previewed.
+    fn preview_transform(&self, cmd: EditCommand) -> Value {
+        let mut scratch = self.duplicate();
+        let base_rev = scratch.engine.get_head_rev_id();
+        if !scratch.apply_transform(cmd) {
+            return ObjectBuilder::new()
+                .insert("ok", false)
+                .insert("error", "unsupported")
+                .insert("message", "only commands that just edit the text can be previewed")
+                .unwrap();
+        }
+        let edits = if scratch.engine.get_head_rev_id() == base_rev {
+            Vec::new()
+        } else {
+            scratch.engine.delta_rev_head(base_rev).edits()
+        };
+        ObjectBuilder::new()
+            .insert("ok", true)
+            .insert("edits", Value::Array(edits.into_iter().map(|(iv, text)| {
+                ObjectBuilder::new()
+                    .insert("start", iv.start())
+                    .insert("end", iv.end())
+                    .insert("text", String::from(text))
+                    .unwrap()
+            }).collect()))
+            .unwrap()
+    }
+
+    // Runs a command for preview_transform, returning false if it doesn't just
+    // edit the text.
+    fn apply_transform(&mut self, cmd: EditCommand) -> bool {
+        use rpc::EditCommand::*;
+
+        match cmd {
+            Insert { chars } => self.do_insert(chars),
+            InsertText { chars } => self.insert(chars),
+            InsertCodepoint { ch } => self.insert(&ch.to_string()),
+            InsertLineAbove => self.open_line(false),
+            InsertLineBelow => self.open_line(true),
+            Duplicate { count } => self.duplicate_selection(count),
+            WrapWithTemplate { template } => self.wrap_with_template(template),
+            DeleteForward => self.delete_forward(),
+            DeleteBackward => self.delete_backward(),
+            DeleteToBeginningOfLine => self.delete_to_beginning_of_line(),
+            Delete { motion } => self.do_delete(motion),
+            InsertNewline => self.insert_newline(),
+            InsertTab => self.insert_tab(),
+            DeleteBlankLines => self.delete_blank_lines(),
+            DeleteAllBlankLines => self.delete_all_blank_lines(),
+            DedentToCommon => self.dedent_to_common(),
+            AlignOn { delimiter } => self.align_on(delimiter),
+            TabsToSpaces { tab_size, leading_only } => self.tabs_to_spaces(tab_size, leading_only),
+            SpacesToTabs { tab_size, leading_only } => self.spaces_to_tabs(tab_size, leading_only),
+            PasteMatchCase { chars } => self.paste_match_case(chars),
+            Transpose => self.do_transpose(),
+            TransposeLines => self.transpose_lines(),
+            ReflowParagraph { width } => self.reflow_paragraph(width),
+            ToggleBlockComment { delimiters } => self.do_toggle_block_comment(delimiters),
+            ToggleLineComment { prefix } => self.do_toggle_line_comment(prefix),
+            ToggleCaseSelection => self.toggle_case(),
+            StraightenQuotes => { self.convert_quotes(false); }
+            SmartenQuotes => { self.convert_quotes(true); }
+            EscapeSelection { kind } => { self.escape_selection(kind, false); }
+            UnescapeSelection { kind } => { self.escape_selection(kind, true); }
+            NormalizeLineEndings { to } => self.normalize_line_endings(to),
+            DeleteSurround => self.delete_surround(),
+            _ => return false,
+        }
+        true
+    }
+
+    // Switches the marking of invisible characters, returning whether they are
+    // now marked.
+    fn reveal_control_chars(&mut self) -> bool {
+        self.dirty = true;
+        self.view.toggle_reveal_control_chars()
+    }
+
+    // Escapes the selection (or reverses that, with `unescape`) as a single
+    // edit, leaving the result selected. Malformed escapes leave the text
+    // unchanged and are reported, as `{"ok":false,"error":"malformed"}`.
+    fn escape_selection(&mut self, kind: EscapeKind, unescape: bool) -> Value {
+        let (start, end) = (self.view.sel_min(), self.view.sel_max());
+        let s = self.text.slice_to_string(start, end);
+        let result = match (kind, unescape) {
+            (EscapeKind::Json, false) => Ok(transform::escape_json(&s)),
+            (EscapeKind::Json, true) => transform::unescape_json(&s),
+            (EscapeKind::Uri, false) => Ok(transform::escape_uri(&s)),
+            (EscapeKind::Uri, true) => transform::unescape_uri(&s),
+            (EscapeKind::Html, false) => Ok(transform::escape_html(&s)),
+            (EscapeKind::Html, true) => transform::unescape_html(&s),
+        };
+        match result {
+            Ok(new) => {
+                if new != s {
+                    let new_end = start + new.len();
+                    self.add_delta(Interval::new_closed_open(start, end), Rope::from(new),
+                        start, new_end);
+                }
+                ObjectBuilder::new().insert("ok", true).unwrap()
+            }
+            Err(message) => ObjectBuilder::new()
+                .insert("ok", false)
+                .insert("error", "malformed")
+                .insert("message", message)
+                .unwrap(),
+        }
+    }
+
+    // Converts quotes, dashes and ellipses in the selection to typographic
+    // ones (`smart`) or to ASCII, as a single edit, returning the number of
+    // replacements. The result is selected; with no selection the whole
+    // document is converted and the cursor stays where it was.
+    fn convert_quotes(&mut self, smart: bool) -> usize {
+        let convert = if smart { transform::smarten_quotes } else { transform::straighten_quotes };
+        let collapsed = self.view.sel_start == self.view.sel_end;
+        let (start, end) = if collapsed {
+            (0, self.text.len())
+        } else {
+            (self.view.sel_min(), self.view.sel_max())
+        };
+        let (new, count) = convert(&self.text.slice_to_string(start, end));
+        if count == 0 {
+            return 0;
+        }
+        let (new_start, new_end) = if collapsed {
+            let before = convert(&self.text.slice_to_string(0, self.view.sel_end)).0;
+            let mut cursor = min(before.len(), new.len());
+            while !new.is_char_boundary(cursor) {
+                cursor -= 1;
+            }
+            (cursor, cursor)
+        } else {
+            (start, start + new.len())
+        };
+        self.add_delta(Interval::new_closed_open(start, end), Rope::from(new), new_start, new_end);
+        count
+    }
+
+    // Swaps the case of the selection, leaving the result selected, or with no
+    // selection, of the character after the cursor, moving past it.
+    fn toggle_case(&mut self) {
+        let start = self.view.sel_min();
+        let (end, collapsed) = if self.view.sel_start == self.view.sel_end {
+            match self.text.next_grapheme_offset(start) {
+                Some(end) => (end, true),
+                None => return,
+            }
+        } else {
+            (self.view.sel_max(), false)
+        };
+        let s = self.text.slice_to_string(start, end);
+        let new = transform::toggle_case(&s);
+        let new_end = start + new.len();
+        let new_start = if collapsed { new_end } else { start };
+        if new != s {
+            self.add_delta(Interval::new_closed_open(start, end), Rope::from(new),
+                new_start, new_end);
+        } else if collapsed {
+            self.set_cursor(new_end, true);
+        }
+    }
+
+    // Pastes over the selection, matching the case of a selected word. This is
+    // always its own undo group.
+    fn paste_match_case(&mut self, chars: &str) {
+        let target = self.text.slice_to_string(self.view.sel_min(), self.view.sel_max());
+        self.insert(&transform::match_case(&target, chars));
+    }
+
+    fn yank(&mut self, tab_ctx: &TabCtx) {
+        self.insert(&*String::from(tab_ctx.get_kill_ring()));
+    }
+
+    pub fn do_rpc(&mut self,
+                  cmd: EditCommand,
+                  tab_ctx: TabCtx)
+                  -> Option<Value> {
+
+        use rpc::EditCommand::*;
 
         self.this_edit_type = EditType::Other;
+        self.command_undo_group = None;
+        if self.mark.is_some() {
+            // motions extend the selection from the mark, as with FLAG_SELECT
+            self.modify_selection();
+        }
+
+        let rev_before = self.head_rev_id();
+        let repeatable = repeatable_edit(&cmd);
+        let timing = LOG_TIMING.load(Ordering::Relaxed);
+        let (command, start_time) = if timing {
+            (command_name(&cmd), time::precise_time_ns())
+        } else {
+            (String::new(), 0)
+        };
 
         let result = match cmd {
             RenderLines { first_line, last_line } => {
@@ -663,14 +2613,34 @@ impl Editor {
             }
             Key { chars, flags } => async(self.do_key(chars, flags)),
             Insert { chars } => async(self.do_insert(chars)),
+            InsertText { chars } => async(self.insert(chars)),
+            InsertCodepoint { ch } => async(self.insert(&ch.to_string())),
+            InsertLineAbove => async(self.open_line(false)),
+            Duplicate { count } => async(self.duplicate_selection(count)),
+            InsertLineBelow => async(self.open_line(true)),
+            ToggleOverwrite => async(self.toggle_overwrite()),
+            InsertDateTime { format } => async(self.insert_date_time(format)),
+            WrapWithTemplate { template } => async(self.wrap_with_template(template)),
+            RepeatLastEdit => async(self.repeat_last_edit()),
             DeleteForward => async(self.delete_forward()),
             DeleteBackward => async(self.delete_backward()),
             DeleteToEndOfParagraph => {
                 async(self.delete_to_end_of_paragraph(&tab_ctx))
             }
             DeleteToBeginningOfLine => async(self.delete_to_beginning_of_line()),
+            Delete { motion } => async(self.do_delete(motion)),
             InsertNewline => async(self.insert_newline()),
             InsertTab => async(self.insert_tab()),
+            DeleteBlankLines => async(self.delete_blank_lines()),
+            DeleteAllBlankLines => async(self.delete_all_blank_lines()),
+            DedentToCommon => async(self.dedent_to_common()),
+            AlignOn { delimiter } => async(self.align_on(delimiter)),
+            TabsToSpaces { tab_size, leading_only } => {
+                async(self.tabs_to_spaces(tab_size, leading_only))
+            }
+            SpacesToTabs { tab_size, leading_only } => {
+                async(self.spaces_to_tabs(tab_size, leading_only))
+            }
             MoveUp => async(self.move_up(0)),
             MoveUpAndModifySelection => async(self.move_up(FLAG_SELECT)),
             MoveDown => async(self.move_down(0)),
@@ -695,35 +2665,208 @@ impl Editor {
             PageDownAndModifySelection => {
                 async(self.scroll_page_down(FLAG_SELECT))
             }
-            Open { file_path } => async(self.do_open(file_path)),
-            Save { file_path } => async(self.do_save(file_path)),
+            Open { file_path, line, column } => Some(self.do_open(file_path, line, column)),
+            Revert => Some(self.do_revert()),
+            Save { file_path, force } => Some(self.do_save(file_path, force, &tab_ctx)),
             Scroll { first, last } => async(self.do_scroll(first, last)),
+            Flush => async(self.flush_undo_gc()),
+            RecenterViewport { position } => async(self.recenter_viewport(position)),
             Yank => async(self.yank(&tab_ctx)),
+            PasteMatchCase { chars } => async(self.paste_match_case(chars)),
             Transpose => async(self.do_transpose()),
+            TransposeLines => async(self.transpose_lines()),
+            ReflowParagraph { width } => async(self.reflow_paragraph(width)),
+            ToggleBlockComment { delimiters } => async(self.do_toggle_block_comment(delimiters)),
+            ToggleLineComment { prefix } => async(self.do_toggle_line_comment(prefix)),
             Click { line, column, flags, click_count } => {
                 async(self.do_click(line, column, flags, click_count))
             }
             Drag { line, column, flags } => async(self.do_drag(line, column, flags)),
-            Undo => async(self.do_undo()),
-            Redo => async(self.do_redo()),
+            MoveToLineCol { line, col, modify_selection } => {
+                async(self.move_to_line_col(line, col, modify_selection))
+            }
+            SetDragGranularity { granularity } => async(self.drag_granularity = granularity),
+            SelectRange { start, end } => async(self.select_range(start, end)),
+            CollapseSelection { to_start } => async(self.collapse_selection(to_start)),
+            SetMark => async(self.set_mark()),
+            ClearMark => async(self.clear_mark()),
+            Undo => Some(Value::Bool(self.do_undo())),
+            Redo => Some(Value::Bool(self.do_redo())),
             Cut => Some(self.do_cut()),
             Copy => Some(self.do_copy()),
+            Find { chars, case_sensitive, whole_word } => {
+                self.dirty = true;
+                let n_matches = self.view.set_find(&self.text, chars, case_sensitive, whole_word);
+                Some(Value::U64(n_matches as u64))
+            }
+            TrimSelection => async(self.trim_selection()),
+            SelectNextOccurrence => async(self.select_next_occurrence()),
+            SelectAllMatches => Some(self.select_all_matches()),
+            NextChange => async(self.move_to_change(true)),
+            GotoLastEdit => async(self.goto_last_edit()),
+            NextSibling => async(self.move_to_sibling(true)),
+            PrevSibling => async(self.move_to_sibling(false)),
+            BlockStart => async(self.move_to_block_edge(false)),
+            BlockEnd => async(self.move_to_block_edge(true)),
+            PrevChange => async(self.move_to_change(false)),
+            NextBracket { bracket } => async(self.move_to_bracket(bracket, true)),
+            PrevBracket { bracket } => async(self.move_to_bracket(bracket, false)),
+            ExpandSelection => async(self.expand_selection()),
+            DeleteSurround => async(self.delete_surround()),
+            ExpandToLines => async(self.expand_to_lines()),
+            ContractSelection => async(self.contract_selection()),
+            GetLineEndingInfo => Some(self.line_ending_info()),
+            IsModifiedOnDisk => Some(self.modified_on_disk()),
+            GetViewConfig => Some(self.view_config()),
+            GetSelectionStats => Some(self.selection_stats()),
+            GetCurrentLine => Some(self.current_line()),
+            GetVisibleRange => Some(self.visible_range()),
+            MeasureLine { line } => Some(self.measure_line(line)),
+            OffsetsToLineCols { offsets } => Some(self.offsets_to_line_cols(&offsets)),
+            NextLongLine { limit } => Some(self.next_long_line(limit)),
+            FilterSelection { command } => Some(self.filter_selection(command)),
+            BoundariesNear { offset } => Some(self.boundaries_near(offset)),
+            SummarizeRegion { start_line, end_line } => {
+                Some(self.summarize_region(start_line, end_line))
+            }
+            UnfoldRegion { line } => Some(Value::Bool(self.unfold_region(line))),
+            SetBlockSelection { start_line, start_col, end_line, end_col } => {
+                async(self.set_block_selection(start_line, start_col, end_line, end_col))
+            }
+            ClearBlockSelection => async(self.clear_block_selection()),
+            GetBlockSelection => Some(self.block_selection()),
+            TransposeSelections => async(self.transpose_selections()),
+            ToggleCaseSelection => async(self.toggle_case()),
+            EscapeSelection { kind } => Some(self.escape_selection(kind, false)),
+            StraightenQuotes => Some(Value::U64(self.convert_quotes(false) as u64)),
+            SmartenQuotes => Some(Value::U64(self.convert_quotes(true) as u64)),
+            ShowSelectionBytes => Some(self.selection_bytes()),
+            PreviewTransform { command } => Some(self.preview_transform(*command)),
+            RevealControlChars => Some(Value::Bool(self.reveal_control_chars())),
+            UnescapeSelection { kind } => Some(self.escape_selection(kind, true)),
+            CopyLocation { style } => Some(self.copy_location(style, &tab_ctx)),
+            NormalizeLineEndings { to } => async(self.normalize_line_endings(to)),
+            SetHangingIndent { indent } => async(self.do_set_hanging_indent(indent)),
+            SetWrapMode { wrap_mode } => async(self.do_set_wrap_mode(wrap_mode)),
+            SetWindowWidth { cols } => async(self.do_set_window_width(cols)),
+            SetConfig { settings } => async(self.set_config(settings)),
             DebugRewrap => async(self.debug_rewrap()),
             DebugTestFgSpans => async(self.debug_test_fg_spans()),
             DebugRunPlugin => async(self.debug_run_plugin(&tab_ctx)),
         };
 
+        let command_time = if timing { time::precise_time_ns() } else { 0 };
         // TODO: could defer this until input quiesces - will this help?
         self.commit_delta();
+        if self.head_rev_id() != rev_before {
+            self.schedule_auto_save(&tab_ctx);
+            if let Some(edit) = repeatable {
+                self.record_edit(edit);
+            }
+        }
+        let commit_time = if timing { time::precise_time_ns() } else { 0 };
         self.render(&tab_ctx);
+        let render_time = if timing { time::precise_time_ns() } else { 0 };
         self.last_edit_type = self.this_edit_type;
-        self.gc_undos();
+        if !self.config.defer_undo_gc {
+            self.gc_undos();
+        }
+        if timing {
+            let gc_time = time::precise_time_ns();
+            let micros = |from: u64, to: u64| (to - from) / 1000;
+            ::xi_rpc::write_log("timing", format_args!("{}", ObjectBuilder::new()
+                .insert("command", command)
+                .insert("command_us", micros(start_time, command_time))
+                .insert("commit_us", micros(command_time, commit_time))
+                .insert("render_us", micros(commit_time, render_time))
+                .insert("gc_us", micros(render_time, gc_time))
+                .unwrap()));
+        }
         result
     }
 
     // Note: the following are placeholders for prototyping, and are not intended to
     // deal with asynchrony or be efficient.
 
+    /// The file this buffer was last opened from or saved to.
+    pub fn file_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    // Whether auto-save should write the buffer: it is enabled with a nonzero
+    // interval, and the buffer has a path, isn't read-only, and is modified.
+    fn can_auto_save(&self) -> bool {
+        self.config.auto_save_interval > 0 && self.path.is_some() && !self.read_only
+            && self.is_modified()
+    }
+
+    // Restarts the auto-save delay after an edit, starting the timer if it
+    // isn't already running.
+    fn schedule_auto_save(&mut self, tab_ctx: &TabCtx) {
+        self.last_edit_time = time::precise_time_ns();
+        if !self.auto_save_pending && self.can_auto_save() {
+            self.auto_save_pending = true;
+            tab_ctx.start_auto_save_timer(self.config.auto_save_interval);
+        }
+    }
+
+    /// Called by the auto-save timer when its delay has passed. Saves if there
+    /// have been no edits for the auto-save interval, returning the result of
+    /// the save for the `auto_save` notification, or otherwise says how much
+    /// longer to wait.
+    pub fn auto_save(&mut self) -> AutoSave {
+        if !self.can_auto_save() {
+            self.auto_save_pending = false;
+            return AutoSave::Cancelled;
+        }
+        let interval_ns = self.config.auto_save_interval.saturating_mul(1_000_000);
+        let idle_ns = time::precise_time_ns().saturating_sub(self.last_edit_time);
+        if idle_ns < interval_ns {
+            return AutoSave::Wait((interval_ns - idle_ns).div_ceil(1_000_000));
+        }
+        if self.saving {
+            return AutoSave::Wait(max(self.config.auto_save_interval, 1));
+        }
+        self.auto_save_pending = false;
+        let path = self.path.clone().unwrap();
+        let text = match self.text_to_save(&path, false) {
+            Ok(text) => text,
+            Err(result) => return AutoSave::Saved(path, result),
+        };
+        if text.len() < STREAMING_SAVE_BYTES {
+            let result = self.save_now(&path, &text);
+            return AutoSave::Saved(path, result);
+        }
+        self.saving = true;
+        AutoSave::Streaming(path, text, self.head_rev_id(), self.config.ensure_final_newline)
+    }
+
+    /// Whether the text has changed since it was last opened or saved.
+    pub fn is_modified(&self) -> bool {
+        self.head_rev_id() != self.pristine_rev_id
+    }
+
+    /// Whether a save is being written by a worker thread.
+    pub fn is_saving(&self) -> bool {
+        self.saving
+    }
+
+    /// Whether the file is read-only, as of when it was last opened or saved.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Estimates the memory used by this editor: the length of the text in
+    /// bytes, the number of revisions of undo history, and the number of
+    /// stored line breaks.
+    pub fn memory_info(&self) -> Value {
+        ObjectBuilder::new()
+            .insert("text_bytes", self.text.len())
+            .insert("revisions", self.engine.n_revs())
+            .insert("breaks", self.view.n_breaks())
+            .unwrap()
+    }
+
     pub fn plugin_buf_size(&self) -> usize {
         self.text.len()
     }
@@ -738,6 +2881,36 @@ impl Editor {
         self.text.slice_to_string(start_offset, end_offset)
     }
 
+    /// Replaces `start..end` of revision `rev` (as reported in updates, or the
+    /// current one if `None`) with `text`, for a plugin with edit priority
+    /// `priority`, as its own undo group. The edit is merged with any made since
+    /// `rev`. Returns false if `rev` is no longer known or the range is invalid.
+    pub fn plugin_edit(&mut self, rev: Option<usize>, start: usize, end: usize, text: &str,
+                       priority: usize) -> bool {
+        let base_rev = match rev {
+            Some(rev) if rev >= self.rev_id_base => rev - self.rev_id_base,
+            Some(_) => return false,
+            None => self.engine.get_head_rev_id(),
+        };
+        let base = match self.engine.get_rev_text(base_rev) {
+            Some(base) => base,
+            None => return false,
+        };
+        let is_boundary = |offset: usize| offset == base.len() ||
+            base.prev_codepoint_offset(offset + 1) == Some(offset);
+        if start > end || end > base.len() || !is_boundary(start) || !is_boundary(end) {
+            return false;
+        }
+        let delta = Delta::simple_edit(Interval::new_closed_open(start, end), Rope::from(text),
+                                       base.len());
+        self.this_edit_type = EditType::Other;
+        self.command_undo_group = None;
+        self.add_delta_at(priority, base_rev, delta);
+        self.command_undo_group = None;
+        self.commit_delta();
+        true
+    }
+
     pub fn plugin_set_line_fg_spans(&mut self, line_num: usize, spans: &Value) {
         let start_offset = self.text.offset_of_line(line_num);
         let end_offset = self.text.offset_of_line(line_num + 1);
@@ -756,6 +2929,195 @@ impl Editor {
     }
 }
 
+// The logical line and byte column of each of `offsets`, found in one pass
+// over the lines; offsets past the end clamp to it.
+fn line_cols(text: &Rope, offsets: &[usize]) -> Vec<(usize, usize)> {
+    let mut order: Vec<usize> = (0..offsets.len()).collect();
+    order.sort_by_key(|&i| offsets[i]);
+    let mut result = vec![(0, 0); offsets.len()];
+    // the cursor also stops at the end of the text, which isn't a line start
+    // unless the text ends with a newline
+    let n_breaks = text.measure::<LinesMetric>();
+    let mut cursor = Cursor::new(text, 0);
+    let (mut line, mut line_start) = (0, 0);
+    let mut next_line_start = cursor.next::<LinesMetric>();
+    for i in order {
+        let offset = min(offsets[i], text.len());
+        while let Some(start) = next_line_start {
+            if start > offset || line == n_breaks {
+                break;
+            }
+            line += 1;
+            line_start = start;
+            next_line_start = cursor.next::<LinesMetric>();
+        }
+        result[i] = (line, offset - line_start);
+    }
+    result
+}
+
+// The command as recorded for repeat_last_edit, if it can be repeated.
+fn repeatable_edit(cmd: &EditCommand) -> Option<RepeatableEdit> {
+    use rpc::EditCommand::*;
+
+    match *cmd {
+        // the other keys, such as the arrows, don't change the text, so
+        // aren't recorded
+        Key { chars: "\r", .. } => Some(RepeatableEdit::InsertNewline),
+        Key { chars: "\x7f", .. } => Some(RepeatableEdit::DeleteBackward),
+        Key { chars, .. } => Some(RepeatableEdit::Insert(chars.to_string())),
+        Insert { chars } | InsertText { chars } => Some(RepeatableEdit::Insert(chars.to_string())),
+        InsertCodepoint { ch } => Some(RepeatableEdit::Insert(ch.to_string())),
+        InsertNewline => Some(RepeatableEdit::InsertNewline),
+        InsertTab => Some(RepeatableEdit::InsertTab),
+        DeleteForward => Some(RepeatableEdit::DeleteForward),
+        DeleteBackward => Some(RepeatableEdit::DeleteBackward),
+        DeleteToBeginningOfLine => Some(RepeatableEdit::DeleteToBeginningOfLine),
+        Delete { motion } => Some(RepeatableEdit::Delete(motion)),
+        _ => None,
+    }
+}
+
+// The name of the variant of an edit command, such as `Insert`.
+fn command_name(cmd: &EditCommand) -> String {
+    let debug = format!("{:?}", cmd);
+    let len = debug.find(|c: char| !c.is_alphanumeric()).unwrap_or(debug.len());
+    debug[..len].to_string()
+}
+
+// Splits a line into its content and its line ending, if it has one.
+fn split_line_ending(line: &str) -> (&str, &str) {
+    let content = line.trim_end_matches(&['\n', '\r'][..]);
+    line.split_at(content.len())
+}
+
+// The size and modification time of the file at `path`, if it can be read.
+fn file_stamp(path: &str) -> Option<(u64, SystemTime)> {
+    fs::metadata(path).ok()
+        .and_then(|metadata| metadata.modified().ok().map(|mtime| (metadata.len(), mtime)))
+}
+
+// A writer that, instead of writing, compares what it is given with the
+// contents of a file, a piece at a time.
+struct ContentComparer {
+    file: File,
+    differs: bool,
+}
+
+impl Write for ContentComparer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.differs {
+            let mut expected = vec![0; buf.len()];
+            match self.file.read_exact(&mut expected) {
+                Ok(()) => self.differs = expected != buf,
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => self.differs = true,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn is_read_only(path: &str) -> bool {
+    fs::metadata(path).map(|m| m.permissions().readonly()).unwrap_or(false)
+}
+
+// The result reporting a failure to read or write a file.
+fn io_error(e: &io::Error) -> Value {
+    print_err!("error {}", e);
+    ObjectBuilder::new()
+        .insert("ok", false)
+        .insert("error", "io")
+        .insert("message", e.to_string())
+        .unwrap()
+}
+
+// Runs `command` with `sh -c`, with `input` on its stdin, returning its stdout,
+// or the result reporting why it failed.
+fn run_filter(command: &str, input: String) -> Result<String, Value> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| io_error(&e))?;
+    // written from another thread, so that a command writing a lot of output
+    // before it has read all its input can't deadlock
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output().map_err(|e| io_error(&e))?;
+    // a command that exits without reading all its input breaks the pipe; what
+    // it printed still counts
+    let _ = writer.join();
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ObjectBuilder::new()
+            .insert("ok", false)
+            .insert("error", "failed")
+            .insert("message", format!("{} ({})", stderr.trim_end(), output.status))
+            .unwrap());
+    }
+    String::from_utf8(output.stdout).map_err(|_| ObjectBuilder::new()
+        .insert("ok", false)
+        .insert("error", "malformed")
+        .insert("message", format!("output of {} is not UTF-8", command))
+        .unwrap())
+}
+
+// Writes `text` as saved to a file: with `ensure_final_newline`, a non-empty
+// text that doesn't end with a newline gets one, without changing the buffer.
+fn write_text<W: Write>(w: &mut W, text: &Rope, ensure_final_newline: bool) -> io::Result<()> {
+    write_text_with_progress(w, text, ensure_final_newline, |_| ())
+}
+
+// Like write_text, calling `progress` with the number of bytes written so far
+// each time another SAVE_PROGRESS_BYTES have been.
+fn write_text_with_progress<W, F>(w: &mut W, text: &Rope, ensure_final_newline: bool,
+        mut progress: F) -> io::Result<()>
+        where W: Write, F: FnMut(usize) {
+    let (mut written, mut reported) = (0, 0);
+    for chunk in text.iter_chunks(0, text.len()) {
+        w.write_all(chunk.as_bytes())?;
+        written += chunk.len();
+        if written - reported >= SAVE_PROGRESS_BYTES {
+            progress(written);
+            reported = written;
+        }
+    }
+    if ensure_final_newline && text.len() > 0 && text.byte_at(text.len() - 1) != b'\n' {
+        w.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Writes `text` to a temporary file next to `path`, then renames it over
+/// `path`, so that a failure part way leaves any existing file intact. The
+/// file keeps the permissions of the one it replaces. `progress` is called as
+/// for write_text_with_progress.
+pub fn save_atomically<F>(path: &str, text: &Rope, ensure_final_newline: bool, progress: F)
+        -> io::Result<()>
+        where F: FnMut(usize) {
+    let temp_path = format!("{}.xi-save", path);
+    let result = File::create(&temp_path).and_then(|mut f| {
+        write_text_with_progress(&mut f, text, ensure_final_newline, progress)?;
+        f.sync_all()?;
+        if let Ok(metadata) = fs::metadata(path) {
+            f.set_permissions(metadata.permissions())?;
+        }
+        Ok(())
+    }).and_then(|_| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
 // wrapper so async methods don't have to return None themselves
 fn async(_: ()) -> Option<Value> {
     None
@@ -766,3 +3128,383 @@ fn n_spaces(n: usize) -> &'static str {
     assert!(n <= spaces.len());
     &spaces[..n]
 }
+
+#[cfg(test)]
+mod tests {
+    use xi_rope::rope::Rope;
+    use xi_rope::interval::Interval;
+    use std::env;
+    use std::fs;
+    use std::io;
+    use std::process;
+    use serde_json::Value;
+    use serde_json;
+    use config::Setting;
+    use rpc::EditCommand;
+    use editor::{Editor, PLUGIN_EDIT_PRIORITY, SAVE_PROGRESS_BYTES, is_plugin_edit_priority,
+        line_cols, save_atomically, write_text, write_text_with_progress};
+    // renamed, as the test of plugin edits has its name
+    use editor::plugin_edit_priority as plugin_priority;
+    use editor::{EditType, RepeatableEdit, repeatable_edit};
+
+    // A path in the temporary directory for a test to use, removing any file
+    // left there by an earlier run.
+    fn temp_path(name: &str) -> String {
+        let path = env::temp_dir().join(format!("xi-test-{}-{}", process::id(), name));
+        let _ = fs::remove_file(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    fn saved(s: &str, ensure_final_newline: bool) -> String {
+        let mut out = Vec::new();
+        write_text(&mut out, &Rope::from(s), ensure_final_newline).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn ensure_final_newline() {
+        assert_eq!("a\nb\n", saved("a\nb", true));
+        assert_eq!("a\nb\n", saved("a\nb\n", true));
+        assert_eq!("a\r\n", saved("a\r\n", true));
+        assert_eq!("", saved("", true));
+        assert_eq!("a\nb", saved("a\nb", false));
+    }
+
+    #[test]
+    fn save_progress() {
+        let text = Rope::from("x".repeat(SAVE_PROGRESS_BYTES * 5 / 2));
+        let mut reports = Vec::new();
+        write_text_with_progress(&mut io::sink(), &text, false, |n| reports.push(n)).unwrap();
+        assert_eq!(2, reports.len());
+        assert!(reports[0] >= SAVE_PROGRESS_BYTES && reports[0] < SAVE_PROGRESS_BYTES * 2);
+        assert!(reports[1] - reports[0] >= SAVE_PROGRESS_BYTES && reports[1] < text.len());
+    }
+
+    #[test]
+    fn save_replaces_atomically() {
+        let path = temp_path("save-atomically");
+        let temp = format!("{}.xi-save", path);
+        fs::write(&path, "old").unwrap();
+        save_atomically(&path, &Rope::from("new"), true, |_| ()).unwrap();
+        assert_eq!("new\n", fs::read_to_string(&path).unwrap());
+        assert!(fs::symlink_metadata(&temp).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    // A temporary file that can't be written, as it links to /dev/full, makes
+    // the save fail part way.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn failed_save_keeps_file() {
+        use std::os::unix::fs::symlink;
+        let path = temp_path("failed-save");
+        let temp = format!("{}.xi-save", path);
+        fs::write(&path, "old").unwrap();
+        let _ = fs::remove_file(&temp);
+        symlink("/dev/full", &temp).unwrap();
+        assert!(save_atomically(&path, &Rope::from("new"), false, |_| ()).is_err());
+        assert_eq!("old", fs::read_to_string(&path).unwrap());
+        assert!(fs::symlink_metadata(&temp).is_err());
+        // the same for a buffer small enough to be saved without streaming
+        symlink("/dev/full", &temp).unwrap();
+        let mut editor = Editor::with_text(Rope::from("new"));
+        let text = editor.text.clone();
+        let result = editor.save_now(&path, &text);
+        assert_eq!(Some("io"), result.find("error").and_then(|e| e.as_string()));
+        assert_eq!("old", fs::read_to_string(&path).unwrap());
+        assert!(fs::symlink_metadata(&temp).is_err());
+        assert_eq!(None, editor.path);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn plugin_edit_priority() {
+        let mut editor = Editor::with_text(Rope::from("ac"));
+        let rev = editor.head_rev_id();
+        // the user types at the position a plugin then edits, as of before
+        editor.add_delta(Interval::new_closed_open(1, 1), Rope::from("X"), 2, 2);
+        editor.commit_delta();
+        assert!(editor.plugin_edit(Some(rev), 1, 1, "b", PLUGIN_EDIT_PRIORITY));
+        assert_eq!("abXc", String::from(&editor.text));
+        assert!(!editor.plugin_edit(Some(rev), 0, 3, "", PLUGIN_EDIT_PRIORITY));
+        assert!(!editor.plugin_edit(Some(rev + 10), 0, 0, "", PLUGIN_EDIT_PRIORITY));
+        // of two plugins inserting at the same position, the higher goes after
+        let rev = editor.head_rev_id();
+        assert!(editor.plugin_edit(Some(rev), 0, 0, "2", plugin_priority(2)));
+        assert!(editor.plugin_edit(Some(rev), 0, 0, "1", plugin_priority(1)));
+        assert_eq!("12abXc", String::from(&editor.text));
+    }
+
+    #[test]
+    fn plugin_edit_priorities() {
+        assert_eq!(PLUGIN_EDIT_PRIORITY, plugin_priority(0));
+        assert!(plugin_priority(1) > plugin_priority(0));
+        assert!(is_plugin_edit_priority(plugin_priority(0x7fff)));
+        assert_eq!(PLUGIN_EDIT_PRIORITY, plugin_priority(0x8000));
+        assert!(!is_plugin_edit_priority(PLUGIN_EDIT_PRIORITY - 1));
+        assert!(!is_plugin_edit_priority(0x10000));
+    }
+
+    #[test]
+    fn batched_line_cols() {
+        let text = Rope::from("ab\ncd\n\nef");
+        assert_eq!(vec![(3, 1), (0, 0), (1, 1), (2, 0), (3, 2), (1, 0)],
+            line_cols(&text, &[8, 0, 4, 6, 99, 3]));
+        assert_eq!(vec![(1, 0)], line_cols(&Rope::from("a\n"), &[2]));
+        assert_eq!(vec![(0, 0)], line_cols(&Rope::from(""), &[5]));
+    }
+
+    #[test]
+    fn undo_restores_selection() {
+        let mut editor = Editor::with_text(Rope::from("abc"));
+        editor.set_selection(1, 2);
+        editor.add_delta(Interval::new_closed_open(1, 2), Rope::from("XY"), 3, 3);
+        editor.commit_delta();
+        editor.set_selection(0, 0);
+        assert!(editor.do_undo());
+        assert_eq!("abc", String::from(&editor.text));
+        assert_eq!((1, 2), (editor.view.sel_start, editor.view.sel_end));
+        assert!(editor.do_redo());
+        assert_eq!("aXYc", String::from(&editor.text));
+        assert_eq!((3, 3), (editor.view.sel_start, editor.view.sel_end));
+    }
+
+    #[test]
+    fn toggle_line_comment() {
+        let text = "fn f() {\n    a();\n\n  b();\n}\n";
+        let mut editor = Editor::with_text(Rope::from(text));
+        editor.set_config(vec![Setting::Language(Some("rust".to_string()))]);
+        editor.set_selection(9, 24);
+        editor.do_toggle_line_comment(None);
+        editor.commit_delta();
+        assert_eq!("fn f() {\n  //   a();\n\n  // b();\n}\n", String::from(&editor.text));
+        assert_eq!((9, 30), (editor.view.sel_start, editor.view.sel_end));
+        editor.do_toggle_line_comment(None);
+        editor.commit_delta();
+        assert_eq!(text, String::from(&editor.text));
+        assert_eq!((9, 24), (editor.view.sel_start, editor.view.sel_end));
+        // the cursor's line, with the prefix of another language
+        let mut editor = Editor::with_text(Rope::from("x = 1\ny = 2\n"));
+        editor.set_config(vec![Setting::Language(Some("python".to_string()))]);
+        editor.set_selection(8, 8);
+        editor.do_toggle_line_comment(None);
+        editor.commit_delta();
+        assert_eq!("x = 1\n# y = 2\n", String::from(&editor.text));
+        assert_eq!((10, 10), (editor.view.sel_start, editor.view.sel_end));
+        // an unknown language needs an explicit prefix
+        editor.set_config(vec![Setting::Language(Some("brainfuck".to_string()))]);
+        editor.do_toggle_line_comment(None);
+        editor.commit_delta();
+        assert_eq!("x = 1\n# y = 2\n", String::from(&editor.text));
+        editor.set_selection(0, 0);
+        editor.do_toggle_line_comment(Some("--"));
+        editor.commit_delta();
+        assert_eq!("-- x = 1\n# y = 2\n", String::from(&editor.text));
+    }
+
+    #[test]
+    fn filter_selection() {
+        let error = |result: &Value| result.find("error").and_then(Value::as_string)
+            .map(|error| error.to_string());
+        let mut editor = Editor::with_text(Rope::from("abc def"));
+        editor.set_selection(0, 3);
+        let result = editor.filter_selection("tr a-z A-Z");
+        assert_eq!(Some("disabled".to_string()), error(&result));
+        editor.set_config(vec![Setting::AllowFilterCommands(true)]);
+        let result = editor.filter_selection("tr a-z A-Z");
+        assert_eq!(Some(true), result.find("ok").and_then(Value::as_boolean));
+        editor.commit_delta();
+        assert_eq!("ABC def", String::from(&editor.text));
+        assert_eq!((0, 3), (editor.view.sel_start, editor.view.sel_end));
+        let result = editor.filter_selection("echo oops >&2; exit 3");
+        assert_eq!(Some("failed".to_string()), error(&result));
+        let message = result.find("message").and_then(Value::as_string).unwrap();
+        assert!(message.starts_with("oops ("));
+        assert_eq!("ABC def", String::from(&editor.text));
+    }
+
+    #[test]
+    fn command_undo_group() {
+        let mut editor = Editor::with_text(Rope::from("abc"));
+        // one command making two edits, then another command
+        editor.add_delta(Interval::new_closed_open(0, 0), Rope::from("X"), 1, 1);
+        editor.add_delta(Interval::new_closed_open(4, 4), Rope::from("Y"), 5, 5);
+        editor.commit_delta();
+        editor.command_undo_group = None;
+        editor.add_delta(Interval::new_closed_open(0, 1), Rope::from(""), 0, 0);
+        editor.commit_delta();
+        assert_eq!("abcY", String::from(&editor.text));
+        assert!(editor.do_undo());
+        assert_eq!("XabcY", String::from(&editor.text));
+        assert!(editor.do_undo());
+        assert_eq!("abc", String::from(&editor.text));
+        assert!(editor.do_redo());
+        assert_eq!("XabcY", String::from(&editor.text));
+    }
+
+    // Runs `cmd` as do_rpc does, as far as recording it for repeat_last_edit.
+    fn run_recorded(editor: &mut Editor, cmd: EditCommand, run: fn(&mut Editor)) {
+        editor.this_edit_type = EditType::Other;
+        editor.command_undo_group = None;
+        let rev = editor.head_rev_id();
+        run(editor);
+        editor.commit_delta();
+        if editor.head_rev_id() != rev {
+            editor.record_edit(repeatable_edit(&cmd).unwrap());
+        }
+        editor.last_edit_type = editor.this_edit_type;
+    }
+
+    #[test]
+    fn repeat_typed_keys() {
+        let mut editor = Editor::with_text(Rope::from(""));
+        run_recorded(&mut editor, EditCommand::Key { chars: "a", flags: 0 },
+            |editor| editor.do_key("a", 0));
+        run_recorded(&mut editor, EditCommand::Key { chars: "b", flags: 0 },
+            |editor| editor.do_key("b", 0));
+        assert_eq!(Some(RepeatableEdit::Insert("ab".to_string())), editor.last_edit);
+        // an arrow key doesn't change the text, so doesn't replace the run
+        run_recorded(&mut editor, EditCommand::Key { chars: "\u{F702}", flags: 0 },
+            |editor| editor.do_key("\u{F702}", 0));
+        editor.command_undo_group = None;
+        editor.repeat_last_edit();
+        editor.commit_delta();
+        assert_eq!("aabb", String::from(&editor.text));
+        run_recorded(&mut editor, EditCommand::Key { chars: "\x7f", flags: 0 },
+            |editor| editor.do_key("\x7f", 0));
+        assert_eq!(Some(RepeatableEdit::DeleteBackward), editor.last_edit);
+    }
+
+    #[test]
+    fn preview_transform() {
+        let mut editor = Editor::with_text(Rope::from("abc"));
+        editor.set_selection(1, 2);
+        let rev = editor.head_rev_id();
+        let result = editor.preview_transform(EditCommand::ToggleCaseSelection);
+        assert_eq!(r#"{"edits":[{"end":2,"start":1,"text":"B"}],"ok":true}"#,
+            serde_json::to_string(&result).unwrap());
+        assert_eq!("abc", String::from(&editor.text));
+        assert_eq!((1, 2), (editor.view.sel_start, editor.view.sel_end));
+        assert_eq!(rev, editor.head_rev_id());
+        assert!(!editor.do_undo());
+        let result = editor.preview_transform(EditCommand::ToggleOverwrite);
+        assert_eq!(Some(false), result.find("ok").and_then(Value::as_boolean));
+    }
+
+    #[test]
+    fn goal_column() {
+        let mut editor = Editor::with_text(Rope::from("abcd\nx\nabcd"));
+        editor.set_cursor(3, true);
+        editor.move_down(0);
+        assert_eq!(6, editor.view.sel_end);
+        editor.move_down(0);
+        assert_eq!(10, editor.view.sel_end);
+        editor.move_down(0);
+        assert_eq!(11, editor.view.sel_end);
+        editor.move_up(0);
+        assert_eq!(6, editor.view.sel_end);
+        editor.move_up(0);
+        assert_eq!(3, editor.view.sel_end);
+        editor.move_up(0);
+        assert_eq!(0, editor.view.sel_end);
+        editor.move_down(0);
+        assert_eq!(6, editor.view.sel_end);
+        editor.move_down(0);
+        assert_eq!(10, editor.view.sel_end);
+    }
+
+    #[test]
+    fn modified_on_disk() {
+        let path = temp_path("modified-on-disk");
+        let checked = |result: Value| (result.find("differs").and_then(Value::as_boolean).unwrap(),
+            result.find("checked").and_then(Value::as_string).unwrap().to_string());
+        fs::write(&path, "abc").unwrap();
+        let mut editor = Editor::with_text(Rope::from(""));
+        editor.set_config(vec![Setting::EnsureFinalNewline(true)]);
+        editor.do_open(&path, 0, 0);
+        assert_eq!((true, "size".to_string()), checked(editor.modified_on_disk()));
+        let text = editor.transformed_for_save();
+        editor.save_now(&path, &text);
+        assert_eq!((false, "stamp".to_string()), checked(editor.modified_on_disk()));
+        editor.set_config(vec![Setting::EnsureFinalNewline(false)]);
+        assert_eq!((true, "size".to_string()), checked(editor.modified_on_disk()));
+        editor.set_config(vec![Setting::EnsureFinalNewline(true)]);
+        assert_eq!((false, "contents".to_string()), checked(editor.modified_on_disk()));
+        fs::write(&path, "abd\n").unwrap();
+        assert_eq!((true, "contents".to_string()), checked(editor.modified_on_disk()));
+        fs::remove_file(&path).unwrap();
+        assert_eq!((true, "exists".to_string()), checked(editor.modified_on_disk()));
+    }
+
+    #[test]
+    fn transpose_lines() {
+        let transposed = |s: &str, cursor: usize| {
+            let mut editor = Editor::with_text(Rope::from(s));
+            editor.set_selection(cursor, cursor);
+            editor.transpose_lines();
+            editor.commit_delta();
+            (String::from(&editor.text), editor.view.sel_end)
+        };
+        assert_eq!(("b\na\nc".to_string(), 4), transposed("a\nb\nc", 2));
+        assert_eq!(("b\na\nc".to_string(), 4), transposed("a\nb\nc", 0));
+        assert_eq!(("a\nc\nb".to_string(), 5), transposed("a\nb\nc", 5));
+        assert_eq!(("b\na\n".to_string(), 4), transposed("a\nb\n", 4));
+        assert_eq!(("a".to_string(), 0), transposed("a", 0));
+        assert_eq!(("a\n".to_string(), 2), transposed("a\n", 2));
+    }
+
+    #[test]
+    fn transpose_selections() {
+        let mut editor = Editor::with_text(Rope::from("a\ncdef"));
+        editor.set_block_selection(0, 0, 1, 3);
+        editor.transpose_selections();
+        editor.commit_delta();
+        assert_eq!("cde\naf", String::from(&editor.text));
+        assert_eq!((4, 5), (editor.view.sel_start, editor.view.sel_end));
+        let mut editor = Editor::with_text(Rope::from("\u{e9}\u{e8}x\nab"));
+        editor.set_block_selection(0, 0, 1, 4);
+        editor.transpose_selections();
+        editor.commit_delta();
+        assert_eq!("abx\n\u{e9}\u{e8}", String::from(&editor.text));
+        assert_eq!((4, 8), (editor.view.sel_start, editor.view.sel_end));
+    }
+
+    #[test]
+    fn align_on() {
+        let mut editor = Editor::with_text(Rope::from("a = 1\nbcd = 2\nnone\n\tx = 3\n"));
+        editor.align_on("=");
+        assert_eq!("a     = 1\nbcd   = 2\nnone\n\tx = 3\n", String::from(&editor.text));
+        let mut editor = Editor::with_text(Rope::from("xx = 1\ny = 2\nzzz = 3\n"));
+        editor.set_selection(0, 8);
+        editor.align_on("=");
+        editor.commit_delta();
+        assert_eq!("xx = 1\ny  = 2\nzzz = 3\n", String::from(&editor.text));
+        assert_eq!((0, 14), (editor.view.sel_start, editor.view.sel_end));
+    }
+
+    #[test]
+    fn convert_quotes() {
+        let mut editor = Editor::with_text(Rope::from("say \"hi\" -- ok"));
+        editor.set_selection(7, 7);
+        assert_eq!(3, editor.convert_quotes(true));
+        editor.commit_delta();
+        assert_eq!("say \u{201c}hi\u{201d} \u{2014} ok", String::from(&editor.text));
+        assert_eq!((9, 9), (editor.view.sel_start, editor.view.sel_end));
+        editor.set_selection(0, 12);
+        assert_eq!(2, editor.convert_quotes(false));
+        editor.commit_delta();
+        assert_eq!("say \"hi\" \u{2014} ok", String::from(&editor.text));
+        assert_eq!((0, 8), (editor.view.sel_start, editor.view.sel_end));
+    }
+
+    #[test]
+    fn trim_selection() {
+        let mut editor = Editor::with_text(Rope::from(" a b\n \n"));
+        editor.set_selection(7, 0);
+        editor.trim_selection();
+        assert_eq!((4, 1), (editor.view.sel_start, editor.view.sel_end));
+        editor.set_selection(4, 7);
+        editor.trim_selection();
+        assert_eq!((4, 4), (editor.view.sel_start, editor.view.sel_end));
+    }
+}
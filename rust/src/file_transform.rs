@@ -0,0 +1,148 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Named transforms of the whole text, run in order as files are opened (on
+//! the text read) and saved (on the text written).
+
+use std::cmp::max;
+
+use xi_rope::rope::Rope;
+use xi_rope::delta::DeltaBuilder;
+use xi_rope::interval::Interval;
+
+use transform::expand_tabs;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FileTransform {
+    /// Removes spaces and tabs at the ends of lines.
+    TrimTrailingWhitespace,
+    /// Adds a newline at the end of a non-empty text that lacks one.
+    EnsureFinalNewline,
+    /// Replaces CRLF line endings with LF.
+    NormalizeCrlf,
+    /// Replaces tabs in indentation with spaces, up to the next tab stop.
+    TabsToSpaces,
+}
+
+use self::FileTransform::*;
+
+impl FileTransform {
+    pub fn from_name(name: &str) -> Option<FileTransform> {
+        match name {
+            "trim_trailing_whitespace" => Some(TrimTrailingWhitespace),
+            "ensure_final_newline" => Some(EnsureFinalNewline),
+            "normalize_crlf" => Some(NormalizeCrlf),
+            "tabs_to_spaces" => Some(TabsToSpaces),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, text: &Rope, tab_size: usize) -> Rope {
+        match *self {
+            EnsureFinalNewline => {
+                let mut text = text.clone();
+                if text.len() > 0 && text.byte_at(text.len() - 1) != b'\n' {
+                    let len = text.len();
+                    text.edit_str(len, len, "\n");
+                }
+                text
+            }
+            TrimTrailingWhitespace => map_lines(text, |content, ending| {
+                format!("{}{}", content.trim_end_matches(&[' ', '\t'][..]), ending)
+            }),
+            NormalizeCrlf => map_lines(text, |content, ending| {
+                let ending = if ending.is_empty() { "" } else { "\n" };
+                format!("{}{}", content, ending)
+            }),
+            TabsToSpaces => map_lines(text, |content, ending| {
+                format!("{}{}", expand_tabs(content, tab_size, true), ending)
+            }),
+        }
+    }
+}
+
+/// Runs `transforms` over `text`, each on the result of the one before.
+pub fn apply_all(transforms: &[FileTransform], text: Rope, tab_size: usize) -> Rope {
+    transforms.iter().fold(text, |text, transform| transform.apply(&text, tab_size))
+}
+
+/// Removes spaces and tabs at the ends of the lines touching any of `ranges`,
+/// which are sorted and non-overlapping, leaving the other lines alone.
+pub fn trim_lines_in(text: &Rope, ranges: &[(usize, usize)]) -> Rope {
+    let mut builder = DeltaBuilder::new(text.len());
+    let mut next_line = 0;
+    for &(start, end) in ranges {
+        let first = max(text.line_of_offset(start), next_line);
+        let last = text.line_of_offset(end);
+        for line in first..last + 1 {
+            let line_start = text.offset_of_line(line);
+            let line = text.slice_to_string(line_start, text.offset_of_line(line + 1));
+            let content = line.trim_end_matches(&['\n', '\r'][..]);
+            let trimmed = content.trim_end_matches(&[' ', '\t'][..]);
+            if trimmed.len() < content.len() {
+                builder.delete(Interval::new_closed_open(line_start + trimmed.len(),
+                                                         line_start + content.len()));
+            }
+        }
+        next_line = last + 1;
+    }
+    builder.build().apply(text)
+}
+
+// Rebuilds the text from `f` applied to the content and line ending of each
+// line.
+fn map_lines<F: Fn(&str, &str) -> String>(text: &Rope, f: F) -> Rope {
+    let mut result = String::with_capacity(text.len());
+    for line in text.lines_raw(0, text.len()) {
+        let content_len = line.trim_end_matches(&['\n', '\r'][..]).len();
+        let (content, ending) = line.split_at(content_len);
+        result.push_str(&f(content, ending));
+    }
+    Rope::from(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use xi_rope::rope::Rope;
+    use file_transform::{FileTransform, apply_all, trim_lines_in};
+    use file_transform::FileTransform::*;
+
+    fn run(transforms: &[FileTransform], s: &str) -> String {
+        String::from(apply_all(transforms, Rope::from(s), 4))
+    }
+
+    #[test]
+    fn transforms() {
+        assert_eq!("a\n b\r\nc", run(&[TrimTrailingWhitespace], "a \t\n b  \r\nc "));
+        assert_eq!("a\nb\n", run(&[EnsureFinalNewline], "a\nb"));
+        assert_eq!("", run(&[EnsureFinalNewline], ""));
+        assert_eq!("a\nb\n\nc", run(&[NormalizeCrlf], "a\r\nb\n\r\nc"));
+        assert_eq!("    a\tb\n  c", run(&[TabsToSpaces], "\ta\tb\n  c"));
+    }
+
+    #[test]
+    fn pipeline() {
+        let transforms = [NormalizeCrlf, TabsToSpaces, TrimTrailingWhitespace, EnsureFinalNewline];
+        assert_eq!("a\n    b\n", run(&transforms, "a \r\n\tb\t"));
+        assert_eq!(Some(NormalizeCrlf), FileTransform::from_name("normalize_crlf"));
+        assert_eq!(None, FileTransform::from_name("crlf"));
+    }
+
+    #[test]
+    fn trim_edited_lines() {
+        let text = Rope::from("a \nb \nc \r\nd ");
+        assert_eq!("a \nb\nc\r\nd ", String::from(trim_lines_in(&text, &[(3, 3), (6, 8)])));
+        assert_eq!("a\nb \nc \r\nd", String::from(trim_lines_in(&text, &[(0, 1), (11, 11)])));
+    }
+}
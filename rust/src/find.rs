@@ -0,0 +1,305 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The state of a find: the query and its matches, which are kept up to date
+//! as the text is edited.
+
+use std::cmp::{min, max};
+
+use xi_rope::rope::Rope;
+use xi_rope::interval::Interval;
+
+use words::is_word_char;
+
+// Folds case for case-insensitive matching. Going through upper case handles
+// characters whose lower case differs by context or expands, so that `ß`
+// matches `SS` and `ς` matches `Σ`.
+fn fold(c: char) -> impl Iterator<Item = char> {
+    c.to_uppercase().flat_map(char::to_lowercase)
+}
+
+// The length of the match of `query` (already folded, if not `case_sensitive`)
+// at the start of `s`, if there is one. A match ends on a character boundary
+// of `s`.
+fn match_at(s: &str, query: &[char], case_sensitive: bool) -> Option<usize> {
+    if case_sensitive {
+        let mut len = 0;
+        let mut s_chars = s.chars();
+        for &q in query {
+            match s_chars.next() {
+                Some(c) if c == q => len += c.len_utf8(),
+                _ => return None,
+            }
+        }
+        return Some(len);
+    }
+    let mut n_matched = 0;
+    for (i, c) in s.char_indices() {
+        for f in fold(c) {
+            if n_matched == query.len() || f != query[n_matched] {
+                return None;
+            }
+            n_matched += 1;
+        }
+        if n_matched == query.len() {
+            return Some(i + c.len_utf8());
+        }
+    }
+    None
+}
+
+// Whether `pos` in `s` is inside a word, between two word characters, so that
+// a whole-word match can't start or end there.
+fn splits_word(s: &str, pos: usize) -> bool {
+    s[..pos].chars().next_back().is_some_and(is_word_char)
+        && s[pos..].chars().next().is_some_and(is_word_char)
+}
+
+// Finds the matches within `s[start..end]`, looking outside that range only to
+// check word boundaries.
+fn find_in(s: &str, start: usize, end: usize, query: &str, case_sensitive: bool,
+           whole_word: bool) -> Vec<(usize, usize)> {
+    let query: Vec<char> = if case_sensitive {
+        query.chars().collect()
+    } else {
+        query.chars().flat_map(fold).collect()
+    };
+    let mut result = Vec::new();
+    if query.is_empty() {
+        return result;
+    }
+    let mut pos = start;
+    while pos < end {
+        match match_at(&s[pos..end], &query, case_sensitive) {
+            Some(len) if !whole_word || !(splits_word(s, pos) || splits_word(s, pos + len)) => {
+                result.push((pos, pos + len));
+                pos += len;
+            }
+            _ => pos += s[pos..].chars().next().unwrap().len_utf8(),
+        }
+    }
+    result
+}
+
+/// Finds the non-overlapping matches of `query` in `s`, as byte ranges. With
+/// `whole_word`, a match must start and end at word boundaries.
+pub fn find_all(s: &str, query: &str, case_sensitive: bool, whole_word: bool)
+        -> Vec<(usize, usize)> {
+    find_in(s, 0, s.len(), query, case_sensitive, whole_word)
+}
+
+/// The offset of the first occurrence of `needle` in `text` at or after
+/// `from`, searching a chunk of the rope at a time.
+pub fn find_from(text: &Rope, needle: &str, from: usize) -> Option<usize> {
+    // the text searched, starting at `start`, ending with enough of the last
+    // chunk for a match to continue into the next one
+    let mut window = String::new();
+    let mut start = from;
+    for chunk in text.iter_chunks(from, text.len()) {
+        window.push_str(chunk);
+        if let Some(i) = window.find(needle) {
+            return Some(start + i);
+        }
+        let mut keep = window.len().saturating_sub(needle.len().saturating_sub(1));
+        while !window.is_char_boundary(keep) {
+            keep -= 1;
+        }
+        window.drain(..keep);
+        start += keep;
+    }
+    None
+}
+
+pub struct Find {
+    query: String,
+    case_sensitive: bool,
+    whole_word: bool,
+    // sorted and non-overlapping
+    matches: Vec<(usize, usize)>,
+    // a region of the text that has been edited since it was last searched
+    dirty: Option<(usize, usize)>,
+}
+
+impl Find {
+    pub fn new(text: &Rope, query: &str, case_sensitive: bool, whole_word: bool) -> Find {
+        let s = text.slice_to_string(0, text.len());
+        Find {
+            query: query.to_string(),
+            case_sensitive: case_sensitive,
+            whole_word: whole_word,
+            matches: find_all(&s, query, case_sensitive, whole_word),
+            dirty: None,
+        }
+    }
+
+    /// Updates the matches for an edit replacing `iv` with `new_len` bytes of
+    /// text. Matches after the edit are moved; matches overlapping it are
+    /// dropped, and the area is searched again in `update`.
+    pub fn after_edit(&mut self, iv: Interval, new_len: usize) {
+        let (start, end) = iv.start_end();
+        let transform = |offset: usize| {
+            if offset <= start {
+                offset
+            } else if offset >= end {
+                offset - (end - start) + new_len
+            } else {
+                start + new_len
+            }
+        };
+        self.matches = self.matches.iter()
+            .filter(|&&(m_start, m_end)| m_end <= start || m_start >= end)
+            .map(|&(m_start, m_end)| (transform(m_start), transform(m_end)))
+            .collect();
+        self.dirty = Some(match self.dirty {
+            Some((d_start, d_end)) => (min(transform(d_start), start),
+                                       max(transform(d_end), start + new_len)),
+            None => (start, start + new_len),
+        });
+    }
+
+    /// Searches the whole text again, for example after it was replaced.
+    pub fn search_all(&mut self, text: &Rope) {
+        *self = Find::new(text, &self.query, self.case_sensitive, self.whole_word);
+    }
+
+    /// Searches the edited region again, if there is one.
+    pub fn update(&mut self, text: &Rope) {
+        let (d_start, d_end) = match self.dirty.take() {
+            Some(dirty) => dirty,
+            None => return,
+        };
+        // a match may start or end up to a match's length outside the edit;
+        // with case folding, each character of the query may match a
+        // character of up to 4 bytes, and a word boundary check looks one
+        // character further
+        let margin = 4 * (self.query.chars().count() + 1);
+        let mut start = snap(text, d_start.saturating_sub(margin));
+        let mut end = snap(text, min(d_end + margin, text.len()));
+        for &(m_start, m_end) in &self.matches {
+            if m_end >= start && m_start <= end {
+                start = min(start, m_start);
+                end = max(end, m_end);
+            }
+        }
+        // include a character either side, for the word boundary checks
+        let context_start = text.prev_codepoint_offset(start).unwrap_or(start);
+        let context_end = text.next_codepoint_offset(end).unwrap_or(end);
+        let s = text.slice_to_string(context_start, context_end);
+        let found = find_in(&s, start - context_start, end - context_start, &self.query,
+            self.case_sensitive, self.whole_word);
+        let after = self.matches.iter().position(|&(m_start, _)| m_start > end)
+            .unwrap_or(self.matches.len());
+        let before = self.matches.iter().position(|&(_, m_end)| m_end >= start)
+            .unwrap_or(after);
+        let found = found.into_iter()
+            .map(|(m_start, m_end)| (context_start + m_start, context_start + m_end));
+        self.matches.splice(before..after, found);
+    }
+
+    /// The matches, sorted. Call `update` first, after any edits.
+    pub fn matches(&self) -> &[(usize, usize)] {
+        &self.matches
+    }
+}
+
+// Moves `offset` back to a codepoint boundary.
+fn snap(text: &Rope, offset: usize) -> usize {
+    if offset >= text.len() {
+        text.len()
+    } else {
+        text.prev_codepoint_offset(offset + 1).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xi_rope::rope::Rope;
+    use xi_rope::interval::Interval;
+    use find::{Find, find_all, find_from};
+
+    #[test]
+    fn find_all_matches() {
+        assert_eq!(vec![(0, 2), (5, 7)], find_all("ab, aab", "ab", true, false));
+        assert_eq!(vec![(0, 3), (4, 7)], find_all("Foo foo", "foo", false, false));
+        assert_eq!(vec![(4, 7)], find_all("Foo foo", "foo", true, false));
+        assert_eq!(vec![(0, 2), (2, 4)], find_all("aaaaa", "aa", true, false));
+        assert!(find_all("abc", "", true, false).is_empty());
+    }
+
+    #[test]
+    fn case_folding() {
+        assert_eq!(vec![(4, 11)], find_all("the STRASSE", "stra\u{df}e", false, false));
+        assert_eq!(vec![(0, 7)], find_all("stra\u{df}e", "STRASSE", false, false));
+        assert_eq!(vec![(0, 6)], find_all("\u{3a3}\u{39f}\u{3a3}", "\u{3c3}\u{3bf}\u{3c2}", false, false));
+        assert!(find_all("STRASSE", "stra\u{df}e", true, false).is_empty());
+        // a match can't end inside a character that folds to several
+        assert!(find_all("\u{df}", "s", false, false).is_empty());
+    }
+
+    #[test]
+    fn whole_word() {
+        assert!(find_all("category", "cat", true, true).is_empty());
+        assert_eq!(vec![(0, 3), (8, 11)], find_all("cat,dog cat", "cat", true, true));
+        assert_eq!(vec![(10, 13)], find_all("cats cat_ cat", "cat", true, true));
+        assert_eq!(vec![(1, 3)], find_all("(-x)", "-x", true, true));
+    }
+
+    #[test]
+    fn find_across_chunks() {
+        let s = format!("{}needle {}needle", "\u{e9}".repeat(1000), "x".repeat(3000));
+        let text = Rope::from(&s);
+        assert_eq!(Some(2000), find_from(&text, "needle", 0));
+        assert_eq!(Some(5007), find_from(&text, "needle", 2001));
+        assert_eq!(None, find_from(&text, "needle", 5008));
+        assert_eq!(Some(1998), find_from(&text, "\u{e9}n", 4));
+    }
+
+    // Applies an edit to both the text and the find, as the editor does.
+    fn edit(text: &mut Rope, find: &mut Find, start: usize, end: usize, new: &str) {
+        text.edit_str(start, end, new);
+        find.after_edit(Interval::new_closed_open(start, end), new.len());
+        find.update(text);
+    }
+
+    #[test]
+    fn matches_follow_edits() {
+        let mut text = Rope::from("foo bar foo bar foo");
+        let mut find = Find::new(&text, "foo", true, false);
+        assert_eq!(&[(0, 3), (8, 11), (16, 19)], find.matches());
+        // typing before a match moves it
+        edit(&mut text, &mut find, 4, 4, "xx");
+        assert_eq!(&[(0, 3), (10, 13), (18, 21)], find.matches());
+        // breaking a match removes it
+        edit(&mut text, &mut find, 11, 12, "");
+        assert_eq!(&[(0, 3), (17, 20)], find.matches());
+        // and repairing it, or making a new one, finds it again
+        edit(&mut text, &mut find, 11, 11, "o");
+        edit(&mut text, &mut find, 4, 4, "foo");
+        assert_eq!(&[(0, 3), (4, 7), (13, 16), (21, 24)], find.matches());
+        let s = String::from(text.clone());
+        assert_eq!(find_all(&s, "foo", true, false), find.matches().to_vec());
+    }
+
+    #[test]
+    fn whole_word_matches_follow_edits() {
+        let mut text = Rope::from("cat dog cat");
+        let mut find = Find::new(&text, "cat", true, true);
+        assert_eq!(&[(0, 3), (8, 11)], find.matches());
+        // typing just after a match makes it part of a longer word
+        edit(&mut text, &mut find, 3, 3, "s");
+        assert_eq!(&[(9, 12)], find.matches());
+        edit(&mut text, &mut find, 3, 4, "");
+        assert_eq!(&[(0, 3), (8, 11)], find.matches());
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Folded regions: runs of lines hidden behind the line before them, which is
+//! drawn as a placeholder.
+
+use xi_rope::interval::Interval;
+
+/// The hidden parts of folds, as byte ranges from the start of the first hidden
+/// line to the end of the last, kept sorted and non-overlapping.
+#[derive(Default)]
+pub struct Folds {
+    folds: Vec<(usize, usize)>,
+}
+
+impl Folds {
+    pub fn new() -> Folds {
+        Folds::default()
+    }
+
+    /// Hides `start..end`, replacing any folds it overlaps.
+    pub fn add(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        self.folds.retain(|&(f_start, f_end)| f_end <= start || f_start >= end);
+        let ix = self.folds.iter().position(|&(f_start, _)| f_start > start)
+            .unwrap_or(self.folds.len());
+        self.folds.insert(ix, (start, end));
+    }
+
+    /// Removes the folds whose hidden part, or placeholder line ending at
+    /// `line_end`, contains `offset`. Returns whether there were any.
+    pub fn remove_at(&mut self, offset: usize, line_end: usize) -> bool {
+        let n_folds = self.folds.len();
+        self.folds.retain(|&(f_start, f_end)| {
+            !(f_start <= offset && offset < f_end) && f_start != line_end
+        });
+        self.folds.len() != n_folds
+    }
+
+    /// Updates the folds for an edit replacing `iv` with `new_len` bytes.
+    /// Folds the edit touches are removed, so the edit isn't hidden.
+    pub fn after_edit(&mut self, iv: Interval, new_len: usize) {
+        let (start, end) = iv.start_end();
+        self.folds = self.folds.iter()
+            .filter(|&&(f_start, f_end)| start >= f_end || end < f_start)
+            .map(|&(f_start, f_end)| if f_start > end {
+                (f_start - (end - start) + new_len, f_end - (end - start) + new_len)
+            } else {
+                (f_start, f_end)
+            })
+            .collect();
+    }
+
+    pub fn clear(&mut self) {
+        self.folds.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.folds.is_empty()
+    }
+
+    /// The hidden range containing `offset`, if any.
+    pub fn hidden_at(&self, offset: usize) -> Option<(usize, usize)> {
+        self.folds.iter().find(|&&(f_start, f_end)| f_start <= offset && offset < f_end)
+            .cloned()
+    }
+
+    /// The hidden range starting at `offset`, if any; the line ending there
+    /// is the fold's placeholder.
+    pub fn hidden_after(&self, offset: usize) -> Option<(usize, usize)> {
+        self.folds.iter().find(|&&(f_start, _)| f_start == offset).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xi_rope::interval::Interval;
+    use fold::Folds;
+
+    #[test]
+    fn folds_follow_edits() {
+        let mut folds = Folds::new();
+        folds.add(10, 20);
+        folds.add(30, 40);
+        // an edit before a fold moves it
+        folds.after_edit(Interval::new_closed_open(0, 2), 5);
+        assert_eq!(Some((13, 23)), folds.hidden_after(13));
+        // an edit inside one, or to the line break ending its placeholder line,
+        // unfolds it
+        folds.after_edit(Interval::new_closed_open(15, 16), 0);
+        assert_eq!(None, folds.hidden_at(15));
+        assert_eq!(Some((32, 42)), folds.hidden_at(32));
+        folds.after_edit(Interval::new_closed_open(31, 32), 0);
+        assert_eq!(None, folds.hidden_at(32));
+        // an edit just after a fold leaves it alone
+        folds.add(10, 20);
+        folds.after_edit(Interval::new_closed_open(20, 20), 3);
+        assert_eq!(Some((10, 20)), folds.hidden_at(19));
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut folds = Folds::new();
+        folds.add(10, 20);
+        folds.add(15, 30);
+        assert_eq!(None, folds.hidden_at(12));
+        assert_eq!(Some((15, 30)), folds.hidden_at(20));
+        assert!(!folds.remove_at(40, 41));
+        // from the placeholder line
+        assert!(folds.remove_at(5, 15));
+        assert_eq!(None, folds.hidden_at(20));
+    }
+}
@@ -14,6 +14,7 @@
 
 //! Compute line wrapping breaks for text.
 
+use std::cmp::min;
 use time;
 
 use xi_rope::rope::{Rope, RopeInfo};
@@ -65,21 +66,59 @@ impl<'a> LineBreakCursor<'a> {
     }
 }
 
-pub fn linewrap(text: &Rope, cols: usize) -> Breaks {
+// Number of leading spaces and tabs in the logical line starting at `line_start`.
+fn leading_blanks(text: &Rope, line_start: usize) -> usize {
+    let mut offset = line_start;
+    while offset < text.len() {
+        match text.byte_at(offset) {
+            b' ' | b'\t' => offset += 1,
+            _ => break,
+        }
+    }
+    offset - line_start
+}
+
+fn logical_line_start(text: &Rope, offset: usize) -> usize {
+    text.offset_of_line(text.line_of_offset(offset))
+}
+
+/// The width reserved at the start of continuation rows of the logical line
+/// starting at `line_start`: its leading whitespace plus the hanging indent,
+/// capped at half the wrap width so that some text always fits.
+pub fn continuation_indent(text: &Rope, line_start: usize, cols: usize, hanging: usize) -> usize {
+    min(leading_blanks(text, line_start) + hanging, cols / 2)
+}
+
+/// The continuation indent of the row starting at `offset`, or 0 if the row
+/// starts a logical line.
+pub fn row_indent(text: &Rope, offset: usize, cols: usize, hanging: usize) -> usize {
+    let line_start = logical_line_start(text, offset);
+    if line_start == offset {
+        0
+    } else {
+        continuation_indent(text, line_start, cols, hanging)
+    }
+}
+
+pub fn linewrap(text: &Rope, cols: usize, hanging: usize) -> Breaks {
     let start_time = time::now();
     let mut lb_cursor = LineBreakCursor::new(text, 0);
     let mut builder = BreakBuilder::new();
     let mut last_pos = 0;
     let mut last_break_pos = 0;
     let mut width = 0;
+    let mut indent = continuation_indent(text, 0, cols, hanging);
+    // width available to the current row
+    let mut avail = cols;
     loop {
         let (pos, hard) = lb_cursor.next();
         let word_width = pos - last_pos;
-        if width > 0 && width + word_width > cols {
+        if width > 0 && width + word_width > avail {
             builder.add_break(width);
             //print_err!("soft break {}", width);
             last_break_pos += width;
             width = 0;
+            avail = cols - indent;
         }
         width += word_width;
         if hard {
@@ -87,6 +126,8 @@ pub fn linewrap(text: &Rope, cols: usize) -> Breaks {
             //print_err!("hard break {}", width);
             last_break_pos += width;
             width = 0;
+            indent = continuation_indent(text, last_break_pos, cols, hanging);
+            avail = cols;
         }
         last_pos = pos;
         if pos == text.len() { break; }
@@ -99,7 +140,8 @@ pub fn linewrap(text: &Rope, cols: usize) -> Breaks {
 }
 
 // `text` is string _after_ editing.
-pub fn rewrap(breaks: &mut Breaks, text: &Rope, iv: Interval, newsize: usize, cols: usize) {
+pub fn rewrap(breaks: &mut Breaks, text: &Rope, iv: Interval, newsize: usize, cols: usize,
+        hanging: usize) {
     let (edit_iv, new_breaks) = {
         let start_time = time::now();
         let (start, end) = iv.start_end();
@@ -114,6 +156,18 @@ pub fn rewrap(breaks: &mut Breaks, text: &Rope, iv: Interval, newsize: usize, co
         // compute end position in edited rope
         let mut inval_end = bk_cursor.next::<BreaksBaseMetric>().map_or(text.len(), |pos|
             pos - (end - start) + newsize);
+        // If the edit touched the indentation of a logical line, all continuation
+        // rows of that line may move, so we can't stop at a matching soft break
+        // until we're past the next hard break.
+        let edit_end = start + newsize;
+        let in_indent = |offset: usize| {
+            let line_start = logical_line_start(text, offset);
+            offset <= line_start + leading_blanks(text, line_start)
+        };
+        let mut indent_changed = in_indent(start) || in_indent(edit_end);
+        let line_start = logical_line_start(text, inval_start);
+        let mut indent = continuation_indent(text, line_start, cols, hanging);
+        let mut avail = if line_start == inval_start { cols } else { cols - indent };
         let mut lb_cursor = LineBreakCursor::new(text, inval_start);
         let mut builder = BreakBuilder::new();
         let mut last_pos = inval_start;
@@ -122,15 +176,16 @@ pub fn rewrap(breaks: &mut Breaks, text: &Rope, iv: Interval, newsize: usize, co
         loop {
             let (pos, hard) = lb_cursor.next();
             let word_width = pos - last_pos;
-            if width > 0 && width + word_width > cols {
+            if width > 0 && width + word_width > avail {
                 builder.add_break(width);
                 last_break_pos += width;
                 width = 0;
+                avail = cols - indent;
                 while last_break_pos > inval_end {
                     inval_end = bk_cursor.next::<BreaksBaseMetric>().map_or(text.len(), |pos|
                         pos - (end - start) + newsize);
                 }
-                if last_break_pos == inval_end {
+                if last_break_pos == inval_end && !indent_changed {
                     break;
                 }
             }
@@ -140,11 +195,16 @@ pub fn rewrap(breaks: &mut Breaks, text: &Rope, iv: Interval, newsize: usize, co
                 builder.add_break(width);
                 last_break_pos += width;
                 width = 0;
+                indent = continuation_indent(text, last_break_pos, cols, hanging);
+                avail = cols;
+                if last_break_pos > edit_end {
+                    indent_changed = false;
+                }
                 while last_break_pos > inval_end {
                     inval_end = bk_cursor.next::<BreaksBaseMetric>().map_or(text.len(), |pos|
                         pos - (end - start) + newsize);
                 }
-                if last_break_pos == inval_end {
+                if last_break_pos == inval_end && !indent_changed {
                     break;
                 }
             }
@@ -161,3 +221,40 @@ pub fn rewrap(breaks: &mut Breaks, text: &Rope, iv: Interval, newsize: usize, co
     };
     breaks.edit(edit_iv, new_breaks);
 }
+
+#[cfg(test)]
+mod tests {
+    use xi_rope::rope::Rope;
+    use xi_rope::tree::Cursor;
+    use xi_rope::interval::Interval;
+    use xi_rope::breaks::{Breaks, BreaksMetric};
+    use linewrap::{linewrap, rewrap, row_indent};
+
+    fn break_offsets(breaks: &Breaks) -> Vec<usize> {
+        let mut cursor = Cursor::new(breaks, 0);
+        let mut result = Vec::new();
+        while let Some(pos) = cursor.next::<BreaksMetric>() {
+            result.push(pos);
+        }
+        result
+    }
+
+    #[test]
+    fn continuation_rows_reserve_indent() {
+        let text = Rope::from("    aaaa bbbb cccc dddd\nx");
+        assert_eq!(vec![9, 14, 19, 24, 25], break_offsets(&linewrap(&text, 12, 0)));
+        assert_eq!(0, row_indent(&text, 0, 12, 0));
+        assert_eq!(4, row_indent(&text, 9, 12, 0));
+        assert_eq!(6, row_indent(&text, 9, 12, 2));
+        assert_eq!(0, row_indent(&text, 24, 12, 0));
+    }
+
+    #[test]
+    fn rewrap_after_indent_change() {
+        let mut text = Rope::from("aaaa bbbb cccc dddd eeee\nx");
+        let mut breaks = linewrap(&text, 12, 0);
+        text.edit_str(0, 0, "    ");
+        rewrap(&mut breaks, &text, Interval::new_closed_open(0, 0), 4, 12, 0);
+        assert_eq!(break_offsets(&linewrap(&text, 12, 0)), break_offsets(&breaks));
+    }
+}
@@ -16,9 +16,15 @@ extern crate serde;
 extern crate serde_json;
 extern crate time;
 
+use std::cell::RefCell;
+use std::env;
+use std::fs::File;
 use std::io;
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
 
 use serde_json::Value;
+use serde_json::builder::ObjectBuilder;
 
 #[macro_use]
 mod macros;
@@ -27,6 +33,14 @@ mod tabs;
 mod editor;
 mod view;
 mod linewrap;
+mod words;
+mod scope;
+mod config;
+mod find;
+mod changes;
+mod fold;
+mod transform;
+mod file_transform;
 mod rpc;
 mod run_plugin;
 
@@ -37,7 +51,8 @@ extern crate xi_rope;
 extern crate xi_unicode;
 extern crate xi_rpc;
 
-use xi_rpc::{RpcLoop, RpcPeer};
+use xi_rope::rope::Rope;
+use xi_rpc::{Framing, RpcLoop, RpcPeer};
 
 pub type MainPeer = RpcPeer<io::Stdout>;
 
@@ -47,21 +62,124 @@ fn handle_req(request: Request, tabs: &mut Tabs, rpc_peer: MainPeer) -> Option<V
     }
 }
 
+// Reads the document named by `--initial-document <path>`, if given. Since stdin
+// is the RPC channel, piped data has to arrive on a separate path, for example
+// `xi-core --initial-document /dev/fd/3 3< file`.
+fn read_initial_document() -> Option<Rope> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--initial-document" {
+            let path = match args.next() {
+                Some(path) => path,
+                None => {
+                    print_err!("--initial-document requires a path");
+                    return None;
+                }
+            };
+            let mut s = String::new();
+            return match File::open(&path).and_then(|mut f| f.read_to_string(&mut s)) {
+                Ok(_) => Some(Rope::from(s)),
+                Err(e) => {
+                    print_err!("error {} reading initial document {}", e, path);
+                    None
+                }
+            };
+        }
+    }
+    None
+}
+
+// Runs `handle` for RPC `method`, containing a panic in it so that the other
+// tabs carry on. The panic is reported as the result, so that a request still
+// gets a response.
+fn contain_panic<F>(method: &str, params: &Value, handle: F) -> Option<Value>
+        where F: FnOnce() -> Option<Value> {
+    match panic::catch_unwind(AssertUnwindSafe(handle)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let reason = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown cause".to_string());
+            print_err!("Error: panic handling RPC request {} with params {:?}: {}",
+                method, params, reason);
+            Some(ObjectBuilder::new()
+                .insert("ok", false)
+                .insert("error", "panic")
+                .insert("message", format!("panic handling {}: {}", method, reason))
+                .unwrap())
+        }
+    }
+}
+
 fn main() {
+    if env::args().any(|arg| arg == "--log-json") {
+        xi_rpc::set_structured_log(true);
+    }
+    if env::args().any(|arg| arg == "--log-timing") {
+        editor::set_log_timing(true);
+    }
     let mut tabs = Tabs::new();
+    if let Some(text) = read_initial_document() {
+        tabs.set_initial_document(text);
+    }
+    // shared by the request handler and the idle callback, which never overlap
+    let tabs = RefCell::new(tabs);
     let stdin = io::stdin();
     let stdout = io::stdout();
-    let mut rpc_looper = RpcLoop::new(stdout);
+    let framing = if env::args().any(|arg| arg == "--content-length") {
+        Framing::ContentLength
+    } else {
+        Framing::Newline
+    };
+    let mut rpc_looper = RpcLoop::with_framing(stdout, framing);
     let peer = rpc_looper.get_peer();
 
-    rpc_looper.mainloop(|| stdin.lock(),
+    rpc_looper.mainloop_with_idle(|| stdin.lock(),
         |method, params| {
         match Request::from_json(method, params) {
-            Ok(req) => handle_req(req, &mut tabs, peer.clone()),
+            Ok(req) => contain_panic(method, params,
+                || handle_req(req, &mut tabs.borrow_mut(), peer.clone())),
             Err(e) => {
                 print_err!("Error {} decoding RPC request {}", e, method);
                 None
             }
         }
-    });
+    },
+    || tabs.borrow_mut().idle());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use xi_rpc::RpcLoop;
+    use contain_panic;
+
+    // An output that the test can read back after the loop has exited.
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn panic_gets_response() {
+        let output = Output::default();
+        let mut rpc_loop = RpcLoop::new(output.clone());
+        let input = "{\"id\":1,\"method\":\"boom\",\"params\":[]}\n".as_bytes();
+        rpc_loop.mainloop_with_idle(|| input,
+            |method, params| contain_panic(method, params, || panic!("oops")),
+            || ());
+        let sent = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert_eq!("{\"id\":1,\"result\":{\"error\":\"panic\",\"message\":\"panic handling boom: oops\",\
+                    \"ok\":false}}\n", sent);
+    }
 }
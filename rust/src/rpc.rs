@@ -13,12 +13,20 @@
 // limitations under the License.
 
 //! RPC handling for communications with front-end.
+//!
+//! This is the only definition of the front-end protocol's methods and their
+//! parameters, as documented in `doc/frontend.md`; the `xi-rpc` crate only
+//! handles the framing of requests and responses.
 
 use std::collections::BTreeMap;
 use std::error;
 use std::fmt;
+use std::str::FromStr;
 use serde_json::Value;
 
+use config::Setting;
+use view::{RecenterPosition, WrapMode};
+
 // =============================================================================
 //  Request handling
 // =============================================================================
@@ -40,11 +48,18 @@ pub enum Request<'a> {
 }
 
 /// An enum representing a tab command, parsed from JSON.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub enum TabCommand<'a> {
     Edit { tab_name: &'a str, edit_command: EditCommand<'a> },
     NewTab,
+    NewTabWithConfig { config: &'a BTreeMap<String, Value> },
     DeleteTab { tab_name: &'a str },
+    DuplicateTab { tab_name: &'a str },
+    ListTabs,
+    GetMemoryInfo,
+    GetKillRing,
+    SetKillRing { chars: &'a str },
+    ValidateCommand { method: &'a str, params: &'a Value },
 }
 
 /// An enum representing an edit command, parsed from JSON.
@@ -53,10 +68,20 @@ pub enum EditCommand<'a> {
     RenderLines { first_line: usize, last_line: usize },
     Key { chars: &'a str, flags: u64 },
     Insert { chars: &'a str },
+    InsertText { chars: &'a str },
+    InsertCodepoint { ch: char },
+    InsertLineAbove,
+    Duplicate { count: usize },
+    InsertLineBelow,
+    ToggleOverwrite,
+    InsertDateTime { format: &'a str },
+    WrapWithTemplate { template: &'a str },
+    RepeatLastEdit,
     DeleteForward,
     DeleteBackward,
     DeleteToEndOfParagraph,
     DeleteToBeginningOfLine,
+    Delete { motion: EditMotion },
     InsertNewline,
     InsertTab,
     MoveUp,
@@ -81,22 +106,208 @@ pub enum EditCommand<'a> {
     PageUpAndModifySelection,
     ScrollPageDown,
     PageDownAndModifySelection,
-    Open { file_path: &'a str },
-    Save { file_path: &'a str },
+    Open { file_path: &'a str, line: usize, column: usize },
+    Save { file_path: &'a str, force: bool },
+    Revert,
     Scroll { first: i64, last: i64 },
+    RecenterViewport { position: RecenterPosition },
+    Flush,
     Yank,
+    PasteMatchCase { chars: &'a str },
     Transpose,
+    TransposeLines,
+    ToggleBlockComment { delimiters: Option<(&'a str, &'a str)> },
+    ToggleLineComment { prefix: Option<&'a str> },
+    ReflowParagraph { width: usize },
+    DeleteBlankLines,
+    DeleteAllBlankLines,
+    DedentToCommon,
+    AlignOn { delimiter: &'a str },
+    TabsToSpaces { tab_size: usize, leading_only: bool },
+    SpacesToTabs { tab_size: usize, leading_only: bool },
     Click { line: u64, column: u64, flags: u64, click_count: u64 },
     Drag { line: u64, column: u64, flags: u64 },
+    MoveToLineCol { line: usize, col: usize, modify_selection: bool },
+    SetDragGranularity { granularity: Granularity },
+    SelectRange { start: usize, end: usize },
+    CollapseSelection { to_start: bool },
+    SetMark,
+    ClearMark,
+    Find { chars: &'a str, case_sensitive: bool, whole_word: bool },
+    TrimSelection,
+    SelectNextOccurrence,
+    SelectAllMatches,
+    NextChange,
+    GotoLastEdit,
+    NextSibling,
+    PrevSibling,
+    BlockStart,
+    BlockEnd,
+    PrevChange,
+    NextBracket { bracket: char },
+    PrevBracket { bracket: char },
+    ExpandSelection,
+    DeleteSurround,
+    ExpandToLines,
+    ContractSelection,
     Undo,
     Redo,
     Cut,
     Copy,
+    GetLineEndingInfo,
+    IsModifiedOnDisk,
+    GetViewConfig,
+    GetSelectionStats,
+    GetCurrentLine,
+    GetVisibleRange,
+    MeasureLine { line: usize },
+    OffsetsToLineCols { offsets: Vec<usize> },
+    NextLongLine { limit: usize },
+    FilterSelection { command: &'a str },
+    BoundariesNear { offset: usize },
+    SummarizeRegion { start_line: usize, end_line: usize },
+    UnfoldRegion { line: usize },
+    SetBlockSelection { start_line: usize, start_col: usize, end_line: usize, end_col: usize },
+    ClearBlockSelection,
+    GetBlockSelection,
+    TransposeSelections,
+    ToggleCaseSelection,
+    StraightenQuotes,
+    SmartenQuotes,
+    EscapeSelection { kind: EscapeKind },
+    ShowSelectionBytes,
+    PreviewTransform { command: Box<EditCommand<'a>> },
+    RevealControlChars,
+    UnescapeSelection { kind: EscapeKind },
+    CopyLocation { style: LocationStyle },
+    NormalizeLineEndings { to: LineEnding },
+    SetHangingIndent { indent: usize },
+    SetWrapMode { wrap_mode: WrapMode },
+    SetWindowWidth { cols: usize },
+    SetConfig { settings: Vec<Setting> },
     DebugRewrap,
     DebugTestFgSpans,
     DebugRunPlugin,
 }
 
+/// A motion describing the extent of an edit, relative to the cursor.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EditMotion {
+    PrevChar,
+    NextChar,
+    PrevWord,
+    NextWord,
+    PrevSubword,
+    NextSubword,
+    StartOfLine,
+    PrevLine,
+    NextLine,
+}
+
+impl FromStr for EditMotion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        use self::EditMotion::*;
+
+        match s {
+            "prev_char" => Ok(PrevChar),
+            "next_char" => Ok(NextChar),
+            "prev_word" => Ok(PrevWord),
+            "next_word" => Ok(NextWord),
+            "prev_subword" => Ok(PrevSubword),
+            "next_subword" => Ok(NextSubword),
+            "start_of_line" => Ok(StartOfLine),
+            "prev_line" => Ok(PrevLine),
+            "next_line" => Ok(NextLine),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A line ending style.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl FromStr for LineEnding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A syntax for escaping text, for `escape_selection` and
+/// `unescape_selection`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EscapeKind {
+    Json,
+    Uri,
+    Html,
+}
+
+impl FromStr for EscapeKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "json" => Ok(EscapeKind::Json),
+            "uri" => Ok(EscapeKind::Uri),
+            "html" => Ok(EscapeKind::Html),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The unit a drag extends the selection by, for `set_drag_granularity`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Granularity {
+    Char,
+    Word,
+    Line,
+}
+
+impl FromStr for Granularity {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "char" => Ok(Granularity::Char),
+            "word" => Ok(Granularity::Word),
+            "line" => Ok(Granularity::Line),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A format for a reference to a position in a file.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LocationStyle {
+    Line,
+    LineCol,
+    HashLine,
+}
+
+impl FromStr for LocationStyle {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "path:line" => Ok(LocationStyle::Line),
+            "path:line:col" => Ok(LocationStyle::LineCol),
+            "path#Lline" => Ok(LocationStyle::HashLine),
+            _ => Err(()),
+        }
+    }
+}
+
 impl<'a> TabCommand<'a> {
     pub fn from_json(method: &str, params: &'a Value) -> Result<Self, Error> {
         use self::TabCommand::*;
@@ -105,10 +316,36 @@ impl<'a> TabCommand<'a> {
         match method {
             "new_tab" => Ok(NewTab),
 
+            "new_tab_with_config" => params.as_object().and_then(|dict| {
+                dict.get("config").and_then(Value::as_object)
+                    .map(|config| NewTabWithConfig { config: config })
+            }).ok_or(MalformedTabParams(method.to_string(), params.clone())),
+
             "delete_tab" => params.as_object().and_then(|dict| {
                 dict_get_string(dict, "tab").map(|tab_name| DeleteTab { tab_name: tab_name })
             }).ok_or(MalformedTabParams(method.to_string(), params.clone())),
 
+            "duplicate_tab" => params.as_object().and_then(|dict| {
+                dict_get_string(dict, "tab").map(|tab_name| DuplicateTab { tab_name: tab_name })
+            }).ok_or(MalformedTabParams(method.to_string(), params.clone())),
+
+            "list_tabs" => Ok(ListTabs),
+
+            "get_memory_info" => Ok(GetMemoryInfo),
+
+            "get_kill_ring" => Ok(GetKillRing),
+
+            "set_kill_ring" => params.as_object().and_then(|dict| {
+                dict_get_string(dict, "chars").map(|chars| SetKillRing { chars: chars })
+            }).ok_or(MalformedTabParams(method.to_string(), params.clone())),
+
+            "validate_command" => params.as_object().and_then(|dict| {
+                if let (Some(method), Some(params)) =
+                    (dict_get_string(dict, "method"), dict.get("params")) {
+                        Some(ValidateCommand { method: method, params: params })
+                    } else { None }
+            }).ok_or(MalformedTabParams(method.to_string(), params.clone())),
+
             "edit" =>
                 params
                 .as_object()
@@ -157,10 +394,51 @@ impl<'a> EditCommand<'a> {
                 dict_get_string(dict, "chars").map(|chars| Insert { chars: chars })
             }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
 
+            "insert_text" => params.as_object().and_then(|dict| {
+                dict_get_string(dict, "chars").map(|chars| InsertText { chars: chars })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "insert_codepoint" => params.as_object().and_then(|dict| {
+                // from_u32 rejects surrogates and values past U+10FFFF
+                dict_get_u64(dict, "code")
+                    .and_then(|code| if code <= u32::MAX as u64 { Some(code as u32) } else { None })
+                    .and_then(::std::char::from_u32)
+                    .map(|ch| InsertCodepoint { ch: ch })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "insert_line_above" => Ok(InsertLineAbove),
+            "duplicate" => params.as_object().and_then(|dict| {
+                match dict.get("count") {
+                    Some(_) => dict_get_u64(dict, "count").map(|count| count as usize),
+                    None => Some(1),
+                }
+            }).map(|count| Duplicate { count: count })
+                .ok_or(MalformedEditParams(method.to_string(), params.clone())),
+            "insert_line_below" => Ok(InsertLineBelow),
+
+            "toggle_overwrite" => Ok(ToggleOverwrite),
+
+            "insert_date_time" => params.as_object().and_then(|dict| {
+                dict_get_string(dict, "format").map(|format| InsertDateTime { format: format })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "wrap_with_template" => params.as_object().and_then(|dict| {
+                dict_get_string(dict, "template")
+                    .map(|template| WrapWithTemplate { template: template })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "repeat_last_edit" => Ok(RepeatLastEdit),
             "delete_forward" => Ok(DeleteForward),
             "delete_backward" => Ok(DeleteBackward),
             "delete_to_end_of_paragraph" => Ok(DeleteToEndOfParagraph),
             "delete_to_beginning_of_line" => Ok(DeleteToBeginningOfLine),
+
+            "delete" => params.as_object().and_then(|dict| {
+                dict_get_string(dict, "motion")
+                    .and_then(|motion| motion.parse().ok())
+                    .map(|motion| Delete { motion: motion })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
             "insert_newline" => Ok(InsertNewline),
             "insert_tab" => Ok(InsertTab),
             "move_up" => Ok(MoveUp),
@@ -188,13 +466,27 @@ impl<'a> EditCommand<'a> {
             "page_down_and_modify_selection" => Ok(PageDownAndModifySelection),
 
             "open" => params.as_object().and_then(|dict| {
-                dict_get_string(dict, "filename").map(|path| Open { file_path: path })
+                // the position is optional, defaulting to the start of the file
+                let line = dict.get("line").map_or(Some(0), Value::as_u64);
+                let column = dict.get("column").map_or(Some(0), Value::as_u64);
+                if let (Some(path), Some(line), Some(column)) =
+                    (dict_get_string(dict, "filename"), line, column) {
+                        Some(Open { file_path: path, line: line as usize, column: column as usize })
+                    } else { None }
             }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
 
             "save" => params.as_object().and_then(|dict| {
-                dict_get_string(dict, "filename").map(|path| Save { file_path: path })
+                let force = match dict.get("force") {
+                    Some(_) => dict_get_bool(dict, "force"),
+                    None => Some(false),
+                };
+                if let (Some(path), Some(force)) = (dict_get_string(dict, "filename"), force) {
+                    Some(Save { file_path: path, force: force })
+                } else { None }
             }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
 
+            "revert" => Ok(Revert),
+
             "scroll" => params.as_array().and_then(|arr| {
                 if let (Some(first), Some(last)) =
                     (arr_get_i64(arr, 0), arr_get_i64(arr, 1)) {
@@ -204,7 +496,59 @@ impl<'a> EditCommand<'a> {
             }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
 
             "yank" => Ok(Yank),
+
+            "paste_match_case" => params.as_object().and_then(|dict| {
+                dict_get_string(dict, "chars").map(|chars| PasteMatchCase { chars: chars })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
             "transpose" => Ok(Transpose),
+            "transpose_lines" => Ok(TransposeLines),
+
+            "reflow_paragraph" => params.as_object().and_then(|dict| {
+                dict_get_u64(dict, "width").map(|width| ReflowParagraph { width: width as usize })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "delete_blank_lines" => Ok(DeleteBlankLines),
+            "delete_all_blank_lines" => Ok(DeleteAllBlankLines),
+            "dedent_to_common" => Ok(DedentToCommon),
+            "align_on" => params.as_object().and_then(|dict| {
+                dict_get_string(dict, "delimiter").filter(|delimiter| !delimiter.is_empty())
+                    .map(|delimiter| AlignOn { delimiter: delimiter })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "tabs_to_spaces" => params.as_object().and_then(|dict| {
+                indent_conversion_params(dict).map(|(tab_size, leading_only)|
+                    TabsToSpaces { tab_size: tab_size, leading_only: leading_only })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "spaces_to_tabs" => params.as_object().and_then(|dict| {
+                indent_conversion_params(dict).map(|(tab_size, leading_only)|
+                    SpacesToTabs { tab_size: tab_size, leading_only: leading_only })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "toggle_block_comment" => params.as_object().and_then(|dict| {
+                // without delimiters, the editor's language decides them
+                if !dict.contains_key("open") && !dict.contains_key("close") {
+                    return Some(ToggleBlockComment { delimiters: None });
+                }
+                if let (Some(open), Some(close)) =
+                    (dict_get_string(dict, "open"), dict_get_string(dict, "close")) {
+                        if !open.is_empty() && !close.is_empty() {
+                            Some(ToggleBlockComment { delimiters: Some((open, close)) })
+                        } else { None }
+                    } else { None }
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "toggle_line_comment" => params.as_object().and_then(|dict| {
+                // without a prefix, the editor's language decides it
+                if !dict.contains_key("prefix") {
+                    return Some(ToggleLineComment { prefix: None });
+                }
+                if let Some(prefix) = dict_get_string(dict, "prefix") {
+                    if !prefix.is_empty() {
+                        Some(ToggleLineComment { prefix: Some(prefix) })
+                    } else { None }
+                } else { None }
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
 
             "click" => params.as_array().and_then(|arr| {
                 if let (Some(line), Some(column), Some(flags), Some(click_count)) =
@@ -222,10 +566,222 @@ impl<'a> EditCommand<'a> {
                     } else { None }
             }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
 
+            "move_to_line_col" => params.as_object().and_then(|dict| {
+                let modify_selection = match dict.get("modify_selection") {
+                    Some(_) => dict_get_bool(dict, "modify_selection"),
+                    None => Some(false),
+                };
+                if let (Some(line), Some(col), Some(modify_selection)) =
+                    (dict_get_u64(dict, "line"), dict_get_u64(dict, "col"), modify_selection) {
+                        Some(MoveToLineCol { line: line as usize, col: col as usize,
+                            modify_selection: modify_selection })
+                    } else { None }
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "select_range" => params.as_object().and_then(|dict| {
+                if let (Some(start), Some(end)) =
+                    (dict_get_u64(dict, "start"), dict_get_u64(dict, "end")) {
+                        Some(SelectRange { start: start as usize, end: end as usize })
+                    } else { None }
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "collapse_selection" => params.as_object().and_then(|dict| {
+                dict_get_bool(dict, "to_start").map(|to_start| CollapseSelection { to_start: to_start })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "set_mark" => Ok(SetMark),
+            "clear_mark" => Ok(ClearMark),
+
+            "find" => params.as_object().and_then(|dict| {
+                let case_sensitive = match dict.get("case_sensitive") {
+                    Some(_) => dict_get_bool(dict, "case_sensitive"),
+                    None => Some(false),
+                };
+                let whole_word = match dict.get("whole_word") {
+                    Some(_) => dict_get_bool(dict, "whole_word"),
+                    None => Some(false),
+                };
+                if let (Some(chars), Some(case_sensitive), Some(whole_word)) =
+                    (dict_get_string(dict, "chars"), case_sensitive, whole_word) {
+                        Some(Find {
+                            chars: chars,
+                            case_sensitive: case_sensitive,
+                            whole_word: whole_word,
+                        })
+                    } else { None }
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "trim_selection" => Ok(TrimSelection),
+            "select_next_occurrence" => Ok(SelectNextOccurrence),
+            "select_all_matches" => Ok(SelectAllMatches),
+            "next_change" => Ok(NextChange),
+            "goto_last_edit" => Ok(GotoLastEdit),
+            "next_sibling" => Ok(NextSibling),
+            "prev_sibling" => Ok(PrevSibling),
+            "block_start" => Ok(BlockStart),
+            "block_end" => Ok(BlockEnd),
+            "prev_change" => Ok(PrevChange),
+            "next_bracket" => params.as_object().and_then(bracket_param)
+                .map(|bracket| NextBracket { bracket: bracket })
+                .ok_or(MalformedEditParams(method.to_string(), params.clone())),
+            "prev_bracket" => params.as_object().and_then(bracket_param)
+                .map(|bracket| PrevBracket { bracket: bracket })
+                .ok_or(MalformedEditParams(method.to_string(), params.clone())),
+            "expand_selection" => Ok(ExpandSelection),
+            "delete_surround" => Ok(DeleteSurround),
+            "expand_to_lines" => Ok(ExpandToLines),
+            "contract_selection" => Ok(ContractSelection),
+
             "undo" => Ok(Undo),
             "redo" => Ok(Redo),
             "cut" => Ok(Cut),
             "copy" => Ok(Copy),
+            "get_line_ending_info" => Ok(GetLineEndingInfo),
+            "is_modified_on_disk" => Ok(IsModifiedOnDisk),
+            "get_view_config" => Ok(GetViewConfig),
+            "get_selection_stats" => Ok(GetSelectionStats),
+            "get_current_line" => Ok(GetCurrentLine),
+            "get_visible_range" => Ok(GetVisibleRange),
+
+            "measure_line" => params.as_object().and_then(|dict| {
+                dict_get_u64(dict, "line").map(|line| MeasureLine { line: line as usize })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "offsets_to_line_cols" => params.as_object()
+                .and_then(|dict| dict.get("offsets"))
+                .and_then(Value::as_array)
+                .and_then(|offsets| offsets.iter()
+                    .map(|offset| offset.as_u64().map(|offset| offset as usize))
+                    .collect())
+                .map(|offsets| OffsetsToLineCols { offsets: offsets })
+                .ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "next_long_line" => params.as_object().and_then(|dict| {
+                dict_get_u64(dict, "limit").map(|limit| NextLongLine { limit: limit as usize })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "filter_selection" => params.as_object().and_then(|dict| {
+                dict_get_string(dict, "command").map(|command| FilterSelection { command: command })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "boundaries_near" => params.as_object().and_then(|dict| {
+                dict_get_u64(dict, "offset").map(|offset| BoundariesNear { offset: offset as usize })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "summarize_region" => params.as_object().and_then(|dict| {
+                if let (Some(start_line), Some(end_line)) =
+                    (dict_get_u64(dict, "start_line"), dict_get_u64(dict, "end_line")) {
+                        Some(SummarizeRegion {
+                            start_line: start_line as usize,
+                            end_line: end_line as usize,
+                        })
+                    } else { None }
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "unfold_region" => params.as_object().and_then(|dict| {
+                dict_get_u64(dict, "line").map(|line| UnfoldRegion { line: line as usize })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "set_block_selection" => params.as_object().and_then(|dict| {
+                if let (Some(start_line), Some(start_col), Some(end_line), Some(end_col)) =
+                    (dict_get_u64(dict, "start_line"), dict_get_u64(dict, "start_col"),
+                     dict_get_u64(dict, "end_line"), dict_get_u64(dict, "end_col")) {
+                        Some(SetBlockSelection {
+                            start_line: start_line as usize,
+                            start_col: start_col as usize,
+                            end_line: end_line as usize,
+                            end_col: end_col as usize,
+                        })
+                    } else { None }
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "clear_block_selection" => Ok(ClearBlockSelection),
+            "get_block_selection" => Ok(GetBlockSelection),
+            "transpose_selections" => Ok(TransposeSelections),
+
+            "copy_location" => params.as_object().and_then(|dict| {
+                match dict.get("style") {
+                    Some(_) => dict_get_string(dict, "style").and_then(|style| style.parse().ok()),
+                    None => Some(LocationStyle::Line),
+                }.map(|style| CopyLocation { style: style })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "normalize_line_endings" => params.as_object().and_then(|dict| {
+                dict_get_string(dict, "to")
+                    .and_then(|to| to.parse().ok())
+                    .map(|to| NormalizeLineEndings { to: to })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "toggle_case_selection" => Ok(ToggleCaseSelection),
+            "straighten_quotes" => Ok(StraightenQuotes),
+            "smarten_quotes" => Ok(SmartenQuotes),
+
+            "set_drag_granularity" => params.as_object().and_then(|dict| {
+                dict_get_string(dict, "granularity")
+                    .and_then(|granularity| granularity.parse().ok())
+                    .map(|granularity| SetDragGranularity { granularity: granularity })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "show_selection_bytes" => Ok(ShowSelectionBytes),
+
+            "preview_transform" => params.as_object().and_then(|dict| {
+                if let (Some(method), Some(params)) =
+                    (dict_get_string(dict, "method"), dict.get("params")) {
+                        EditCommand::from_json(method, params).ok()
+                    } else { None }
+            }).map(|command| PreviewTransform { command: Box::new(command) })
+            .ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "reveal_control_chars" => Ok(RevealControlChars),
+
+            "escape_selection" => params.as_object().and_then(|dict| {
+                dict_get_string(dict, "kind")
+                    .and_then(|kind| kind.parse().ok())
+                    .map(|kind| EscapeSelection { kind: kind })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "unescape_selection" => params.as_object().and_then(|dict| {
+                dict_get_string(dict, "kind")
+                    .and_then(|kind| kind.parse().ok())
+                    .map(|kind| UnescapeSelection { kind: kind })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "set_hanging_indent" => params.as_object().and_then(|dict| {
+                dict_get_u64(dict, "indent").map(|indent| SetHangingIndent { indent: indent as usize })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "flush" => Ok(Flush),
+
+            "recenter_viewport" => params.as_object().and_then(|dict| {
+                match dict_get_string(dict, "position") {
+                    Some("top") => Some(RecenterPosition::Top),
+                    Some("center") => Some(RecenterPosition::Center),
+                    Some("bottom") => Some(RecenterPosition::Bottom),
+                    _ => None,
+                }.map(|position| RecenterViewport { position: position })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "set_wrap_mode" => params.as_object().and_then(|dict| {
+                match dict_get_string(dict, "mode") {
+                    Some("none") => Some(WrapMode::NoWrap),
+                    Some("window") => Some(WrapMode::Window),
+                    Some("column") => dict_get_u64(dict, "column").and_then(|column|
+                        if column > 0 { Some(WrapMode::Column(column as usize)) } else { None }),
+                    _ => None,
+                }.map(|wrap_mode| SetWrapMode { wrap_mode: wrap_mode })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "set_window_width" => params.as_object().and_then(|dict| {
+                dict_get_u64(dict, "cols").map(|cols| SetWindowWidth { cols: cols as usize })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
+            "set_config" => params.as_object().and_then(|dict| {
+                dict.iter()
+                    .map(|(key, value)| Setting::from_json(key, value))
+                    .collect::<Option<Vec<_>>>()
+                    .map(|settings| SetConfig { settings: settings })
+            }).ok_or(MalformedEditParams(method.to_string(), params.clone())),
+
             "debug_rewrap" => Ok(DebugRewrap),
             "debug_test_fg_spans" => Ok(DebugTestFgSpans),
             "debug_run_plugin" => Ok(DebugRunPlugin),
@@ -248,6 +804,30 @@ pub enum Error {
     MalformedEditParams(String, Value), // method name, malformed params
 }
 
+impl Error {
+    /// A short machine-readable name for the kind of error.
+    pub fn kind(&self) -> &'static str {
+        use self::Error::*;
+
+        match *self {
+            UnknownTabMethod(_) => "unknown_tab_method",
+            MalformedTabParams(_, _) => "malformed_tab_params",
+            UnknownEditMethod(_) => "unknown_edit_method",
+            MalformedEditParams(_, _) => "malformed_edit_params",
+        }
+    }
+
+    /// The name of the method that failed to parse.
+    pub fn method(&self) -> &str {
+        use self::Error::*;
+
+        match *self {
+            UnknownTabMethod(ref method) | MalformedTabParams(ref method, _) |
+            UnknownEditMethod(ref method) | MalformedEditParams(ref method, _) => method,
+        }
+    }
+}
+
 impl fmt::Display for Error {
     // TODO: Provide information about the parameter format expected when
     // displaying malformed parameter errors
@@ -290,6 +870,32 @@ fn dict_get_string<'a>(dict: &'a BTreeMap<String, Value>, key: &str) -> Option<&
     dict.get(key).and_then(Value::as_string)
 }
 
+fn dict_get_bool(dict: &BTreeMap<String, Value>, key: &str) -> Option<bool> {
+    dict.get(key).and_then(Value::as_boolean)
+}
+
+// `tab_size` (required, nonzero) and `leading_only` (defaults to true).
+fn indent_conversion_params(dict: &BTreeMap<String, Value>) -> Option<(usize, bool)> {
+    let leading_only = match dict.get("leading_only") {
+        Some(_) => dict_get_bool(dict, "leading_only"),
+        None => Some(true),
+    };
+    if let (Some(tab_size), Some(leading_only)) = (dict_get_u64(dict, "tab_size"), leading_only) {
+        if tab_size > 0 { Some((tab_size as usize, leading_only)) } else { None }
+    } else { None }
+}
+
+// `kind`, a single bracket character.
+fn bracket_param(dict: &BTreeMap<String, Value>) -> Option<char> {
+    dict_get_string(dict, "kind").and_then(|kind| {
+        let mut chars = kind.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if "()[]{}<>".contains(c) => Some(c),
+            _ => None,
+        }
+    })
+}
+
 fn arr_get_u64(arr: &[Value], idx: usize) -> Option<u64> {
     arr.get(idx).and_then(Value::as_u64)
 }
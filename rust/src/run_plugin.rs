@@ -73,6 +73,15 @@ fn rpc_handler(plugin_ctx: &PluginCtx, method: &str, params: &Value) -> Option<V
             plugin_ctx.set_line_fg_spans(line_num, spans);
             None
         }
+        "edit" => {
+            let dict = params.as_object().unwrap();
+            let rev = dict.get("rev").and_then(Value::as_u64).map(|rev| rev as usize);
+            let start = dict.get("start").and_then(Value::as_u64).unwrap() as usize;
+            let end = dict.get("end").and_then(Value::as_u64).unwrap() as usize;
+            let text = dict.get("text").and_then(Value::as_string).unwrap();
+            let priority = dict.get("priority").and_then(Value::as_u64).map(|p| p as usize);
+            Some(Value::Bool(plugin_ctx.edit(rev, start, end, text, priority)))
+        }
         "alert" => {
             let msg = params.as_object().and_then(|dict| dict.get("msg").and_then(Value::as_string)).unwrap();
             plugin_ctx.alert(msg);
@@ -0,0 +1,219 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Heuristic syntactic scopes (brackets and quotes), found by scanning the
+//! text without any knowledge of the language.
+
+use xi_rope::rope::{Rope, RopeInfo};
+use xi_rope::tree::Cursor;
+
+/// The closing bracket for an opening bracket.
+pub fn closer_of(b: u8) -> Option<u8> {
+    match b {
+        b'(' => Some(b')'),
+        b'[' => Some(b']'),
+        b'{' => Some(b'}'),
+        _ => None,
+    }
+}
+
+fn is_closer(b: u8) -> bool {
+    b == b')' || b == b']' || b == b'}'
+}
+
+// The bytes of a rope before a position, nearest first, with their offsets.
+struct BytesBefore<'a> {
+    cursor: Cursor<'a, RopeInfo>,
+    leaf: &'a [u8],
+    leaf_start: usize,
+    // the end of the bytes of `leaf` not yet returned
+    end: usize,
+}
+
+impl<'a> BytesBefore<'a> {
+    fn new(text: &'a Rope, pos: usize) -> BytesBefore<'a> {
+        let cursor = Cursor::new(text, pos);
+        let (leaf, end) = cursor.get_leaf().map_or((&[][..], 0), |(leaf, offset)|
+            (leaf.as_bytes(), offset));
+        BytesBefore { cursor: cursor, leaf: leaf, leaf_start: pos - end, end: end }
+    }
+}
+
+impl<'a> Iterator for BytesBefore<'a> {
+    type Item = (usize, u8);
+
+    fn next(&mut self) -> Option<(usize, u8)> {
+        while self.end == 0 {
+            let (leaf, _) = self.cursor.prev_leaf()?;
+            self.leaf = leaf.as_bytes();
+            self.leaf_start = self.cursor.pos();
+            self.end = leaf.len();
+        }
+        self.end -= 1;
+        Some((self.leaf_start + self.end, self.leaf[self.end]))
+    }
+}
+
+// The bytes of a rope from a position on, with their offsets.
+fn bytes_from<'a>(text: &'a Rope, pos: usize) -> impl Iterator<Item = (usize, u8)> + 'a {
+    text.iter_chunks(pos, text.len()).flat_map(|chunk| chunk.bytes())
+        .enumerate()
+        .map(move |(i, b)| (pos + i, b))
+}
+
+/// Finds the nearest opening bracket before `pos` that isn't closed before
+/// `pos`.
+pub fn unmatched_open_before(text: &Rope, pos: usize) -> Option<usize> {
+    let mut closers = Vec::new();
+    for (i, b) in BytesBefore::new(text, pos) {
+        if is_closer(b) {
+            closers.push(b);
+        } else if closer_of(b).is_some() {
+            // a mismatched pair is skipped over as if it matched
+            if closers.pop().is_none() {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Finds the bracket closing the one at `open`, skipping nested pairs.
+pub fn matching_close(text: &Rope, open: usize) -> Option<usize> {
+    let mut bytes = bytes_from(text, open);
+    let mut closers = match bytes.next().and_then(|(_, b)| closer_of(b)) {
+        Some(close) => vec![close],
+        None => return None,
+    };
+    for (i, b) in bytes {
+        if let Some(close) = closer_of(b) {
+            closers.push(close);
+        } else if is_closer(b) {
+            if closers.last() == Some(&b) {
+                closers.pop();
+            }
+            if closers.is_empty() {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+fn enclosing_brackets(text: &Rope, start: usize, end: usize) -> Option<(usize, usize)> {
+    let mut pos = start;
+    while let Some(open) = unmatched_open_before(text, pos) {
+        match matching_close(text, open) {
+            Some(close) if close >= end => return Some((open, close)),
+            Some(_) => pos = open,
+            None => return None,
+        }
+    }
+    None
+}
+
+fn is_quote(b: u8) -> bool {
+    b == b'"' || b == b'\'' || b == b'`'
+}
+
+// Quotes only pair up within a line, and a backslash escapes the next byte.
+fn enclosing_quotes(text: &Rope, start: usize, end: usize) -> Option<(usize, usize)> {
+    let line = text.line_of_offset(start);
+    let line_start = text.offset_of_line(line);
+    let line_text = text.slice_to_string(line_start, text.offset_of_line(line + 1));
+    line_quotes(&line_text, start - line_start, end.saturating_sub(line_start))
+        .map(|(open, close)| (line_start + open, line_start + close))
+}
+
+// Like enclosing_quotes, within the text of one line.
+fn line_quotes(text: &str, start: usize, end: usize) -> Option<(usize, usize)> {
+    let bytes = text.as_bytes();
+    let mut open = None;
+    let mut i = 0;
+    while i < start {
+        let b = bytes[i];
+        if b == b'\\' {
+            i += 1;
+        } else if is_quote(b) {
+            match open {
+                None => open = Some((i, b)),
+                Some((_, q)) if q == b => open = None,
+                _ => (),
+            }
+        }
+        i += 1;
+    }
+    open.and_then(|(open, quote)| {
+        let mut i = open + 1;
+        while i < bytes.len() && bytes[i] != b'\n' {
+            if bytes[i] == b'\\' {
+                i += 1;
+            } else if bytes[i] == quote {
+                return if i >= end { Some((open, i)) } else { None };
+            }
+            i += 1;
+        }
+        None
+    })
+}
+
+/// Finds the innermost bracket or quote pair enclosing `start..end`, returning
+/// the offsets of the opening and closing delimiters.
+pub fn enclosing_pair(text: &Rope, start: usize, end: usize) -> Option<(usize, usize)> {
+    match (enclosing_brackets(text, start, end), enclosing_quotes(text, start, end)) {
+        (Some(brackets), Some(quotes)) => Some(if quotes.0 > brackets.0 { quotes } else { brackets }),
+        (brackets, quotes) => brackets.or(quotes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xi_rope::rope::Rope;
+    use scope::{enclosing_pair, matching_close, unmatched_open_before};
+
+    #[test]
+    fn brackets() {
+        let s = &Rope::from("f(a[1], {b}) + (c)");
+        assert_eq!(Some(11), matching_close(s, 1));
+        assert_eq!(Some(5), matching_close(s, 3));
+        assert_eq!(None, matching_close(s, 0));
+        assert_eq!(Some((3, 5)), enclosing_pair(s, 4, 4));
+        assert_eq!(Some((1, 11)), enclosing_pair(s, 3, 6));
+        assert_eq!(Some((1, 11)), enclosing_pair(s, 7, 7));
+        assert_eq!(None, enclosing_pair(s, 13, 13));
+        assert_eq!(None, enclosing_pair(s, 4, 16));
+    }
+
+    #[test]
+    fn brackets_across_leaves() {
+        // long enough for the rope to have several leaves
+        let inner = "(x) ".repeat(1000);
+        let s = &Rope::from(format!("{{{}}}", inner));
+        assert_eq!(Some(0), unmatched_open_before(s, s.len() - 1));
+        assert_eq!(Some(s.len() - 1), matching_close(s, 0));
+        assert_eq!(Some((0, s.len() - 1)), enclosing_pair(s, 2000, 2000));
+    }
+
+    #[test]
+    fn quotes() {
+        let s = &Rope::from("x(\"a \\\" b\", 'c')\n'd'");
+        assert_eq!(Some((2, 9)), enclosing_pair(s, 4, 4));
+        assert_eq!(Some((2, 9)), enclosing_pair(s, 8, 8));
+        assert_eq!(Some((1, 15)), enclosing_pair(s, 10, 10));
+        assert_eq!(Some((12, 14)), enclosing_pair(s, 13, 13));
+        assert_eq!(Some((17, 19)), enclosing_pair(s, 18, 18));
+        // an apostrophe in prose has no partner
+        assert_eq!(None, enclosing_pair(&Rope::from("don't"), 4, 4));
+    }
+}
@@ -15,20 +15,29 @@
 //! A container for all the tabs being edited. Also functions as main dispatch for RPC.
 
 use std::collections::BTreeMap;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
 use serde_json::Value;
 use serde_json::builder::ObjectBuilder;
 
 use xi_rope::rope::Rope;
-use editor::Editor;
+use editor::{AutoSave, Editor, is_plugin_edit_priority, plugin_edit_priority, save_atomically};
 use rpc::{TabCommand, EditCommand};
+use config::Setting;
 use run_plugin::PluginPeer;
 use MainPeer;
 
+// the number of plugins started so far, each given its own edit priority
+static PLUGINS_STARTED: AtomicUsize = AtomicUsize::new(0);
+
 pub struct Tabs {
     tabs: BTreeMap<String, Arc<Mutex<Editor>>>,
     id_counter: usize,
     kill_ring: Arc<Mutex<Rope>>,
+    // contents for the first tab created, if supplied at startup
+    initial_document: Option<Rope>,
 }
 
 #[derive(Clone)]
@@ -42,6 +51,8 @@ pub struct TabCtx {
 pub struct PluginCtx {
     tab_ctx: TabCtx,
     rpc_peer: Option<PluginPeer>,
+    // the engine priority of the plugin's edits, unless it passes its own
+    priority: usize,
 }
 
 impl Tabs {
@@ -50,6 +61,20 @@ impl Tabs {
             tabs: BTreeMap::new(),
             id_counter: 0,
             kill_ring: Arc::new(Mutex::new(Rope::from(""))),
+            initial_document: None,
+        }
+    }
+
+    /// Supplies the contents of the document to be loaded into the next tab
+    /// created by `new_tab`. The resulting buffer is untitled and editable.
+    pub fn set_initial_document(&mut self, text: Rope) {
+        self.initial_document = Some(text);
+    }
+
+    /// Does work deferred until no requests are waiting.
+    pub fn idle(&mut self) {
+        for editor in self.tabs.values() {
+            lock_editor(editor).flush_undo_gc();
         }
     }
 
@@ -59,11 +84,28 @@ impl Tabs {
         match cmd {
             NewTab => Some(Value::String(self.do_new_tab())),
 
+            NewTabWithConfig { config } => Some(Value::String(self.do_new_tab_with_config(config))),
+
             DeleteTab { tab_name } => {
                 self.do_delete_tab(tab_name);
                 None
             },
 
+            DuplicateTab { tab_name } => self.do_duplicate_tab(tab_name).map(Value::String),
+
+            ListTabs => Some(self.do_list_tabs()),
+
+            GetMemoryInfo => Some(self.do_get_memory_info()),
+
+            GetKillRing => Some(Value::String(self.do_get_kill_ring())),
+
+            SetKillRing { chars } => {
+                self.do_set_kill_ring(chars);
+                None
+            },
+
+            ValidateCommand { method, params } => Some(self.do_validate_command(method, params)),
+
             Edit { tab_name, edit_command } => self.do_edit(tab_name, edit_command, rpc_peer),
         }
     }
@@ -72,10 +114,84 @@ impl Tabs {
         self.new_tab()
     }
 
+    // Creates a tab with the given settings already applied, ignoring (with a
+    // warning) any that aren't valid.
+    fn do_new_tab_with_config(&mut self, config: &BTreeMap<String, Value>) -> String {
+        let settings = config.iter().filter_map(|(key, value)| {
+            let setting = Setting::from_json(key, value);
+            if setting.is_none() {
+                print_err!("ignoring invalid setting {}: {:?}", key, value);
+            }
+            setting
+        }).collect();
+        let tabname = self.new_tab();
+        lock_editor(&self.tabs[&tabname]).set_config(settings);
+        tabname
+    }
+
     fn do_delete_tab(&mut self, tab: &str) {
         self.delete_tab(tab);
     }
 
+    fn do_duplicate_tab(&mut self, tab: &str) -> Option<String> {
+        let editor = match self.tabs.get(tab) {
+            Some(editor) => lock_editor(editor).duplicate(),
+            None => {
+                print_err!("tab not found: {}", tab);
+                return None;
+            }
+        };
+        let tabname = self.id_counter.to_string();
+        self.id_counter += 1;
+        self.tabs.insert(tabname.clone(), Arc::new(Mutex::new(editor)));
+        Some(tabname)
+    }
+
+    fn do_list_tabs(&self) -> Value {
+        Value::Array(self.tabs.iter().map(|(name, editor)| {
+            let editor = lock_editor(editor);
+            ObjectBuilder::new()
+                .insert("tab", name)
+                .insert("path", editor.file_path().map_or(Value::Null,
+                    |path| Value::String(path.to_string())))
+                .insert("modified", editor.is_modified())
+                .insert("read_only", editor.is_read_only())
+                .insert("saving", editor.is_saving())
+                .unwrap()
+        }).collect())
+    }
+
+    fn do_get_memory_info(&self) -> Value {
+        Value::Array(self.tabs.iter().map(|(name, editor)| {
+            let mut info = lock_editor(editor).memory_info();
+            if let Value::Object(ref mut map) = info {
+                map.insert("tab".to_string(), Value::String(name.clone()));
+            }
+            info
+        }).collect())
+    }
+
+    fn do_get_kill_ring(&self) -> String {
+        String::from(self.kill_ring.lock().unwrap().clone())
+    }
+
+    fn do_set_kill_ring(&mut self, chars: &str) {
+        *self.kill_ring.lock().unwrap() = Rope::from(chars);
+    }
+
+    // Parses a command without running it, reporting any error.
+    fn do_validate_command(&self, method: &str, params: &Value) -> Value {
+        match TabCommand::from_json(method, params) {
+            Ok(_) => ObjectBuilder::new().insert("ok", true).unwrap(),
+            Err(e) => ObjectBuilder::new()
+                .insert("ok", false)
+                .insert("error", e.kind())
+                .insert("method", e.method())
+                .insert("message", e.to_string())
+                .unwrap(),
+        }
+    }
+
     fn do_edit(&mut self, tab: &str, cmd: EditCommand, rpc_peer: MainPeer)
             -> Option<Value> {
         if let Some(editor) = self.tabs.get(tab) {
@@ -85,7 +201,7 @@ impl Tabs {
                 rpc_peer: rpc_peer,
                 self_ref: editor.clone(),
             };
-            editor.lock().unwrap().do_rpc(cmd, tab_ctx)
+            lock_editor(editor).do_rpc(cmd, tab_ctx)
         } else {
             print_err!("tab not found: {}", tab);
             None
@@ -95,7 +211,10 @@ impl Tabs {
     fn new_tab(&mut self) -> String {
         let tabname = self.id_counter.to_string();
         self.id_counter += 1;
-        let editor = Editor::new();
+        let editor = match self.initial_document.take() {
+            Some(text) => Editor::with_text(text),
+            None => Editor::new(),
+        };
         self.tabs.insert(tabname.clone(), Arc::new(Mutex::new(editor)));
         tabname
     }
@@ -105,6 +224,36 @@ impl Tabs {
     }
 }
 
+// Locks an editor, even if an earlier command panicked while holding the lock,
+// so that one failed command doesn't make its tab unusable.
+fn lock_editor<'a>(editor: &'a Mutex<Editor>) -> MutexGuard<'a, Editor> {
+    editor.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Saves `text`, the text of revision `rev`, to `path` without holding the
+// editor's lock, sending `save_progress` notifications as it goes and a
+// `save_done` notification with the result.
+fn save_with_progress(editor: &Mutex<Editor>, tab: &str, rpc_peer: &MainPeer, path: &str,
+        text: &Rope, rev: usize, ensure_final_newline: bool) {
+    let total = text.len();
+    let result = save_atomically(path, text, ensure_final_newline, |written| {
+        rpc_peer.send_rpc_notification("save_progress",
+            &ObjectBuilder::new()
+                .insert("tab", tab)
+                .insert("path", path)
+                .insert("written", written)
+                .insert("total", total)
+                .unwrap());
+    });
+    let result = lock_editor(editor).finish_save(path, rev, result);
+    rpc_peer.send_rpc_notification("save_done",
+        &ObjectBuilder::new()
+            .insert("tab", tab)
+            .insert("path", path)
+            .insert("result", result)
+            .unwrap());
+}
+
 impl TabCtx {
     pub fn update_tab(&self, update: &Value) {
         self.rpc_peer.send_rpc_notification("update",
@@ -114,6 +263,68 @@ impl TabCtx {
                 .unwrap());
     }
 
+    /// Starts a thread that auto-saves the editor once it has been idle,
+    /// checking first after `delay_ms`. The thread stops when the tab is
+    /// deleted.
+    pub fn start_auto_save_timer(&self, delay_ms: u64) {
+        let editor = Arc::downgrade(&self.self_ref);
+        let tab = self.tab.clone();
+        let rpc_peer = self.rpc_peer.clone();
+        thread::spawn(move || {
+            let mut delay_ms = delay_ms;
+            loop {
+                thread::sleep(Duration::from_millis(delay_ms));
+                let editor = match editor.upgrade() {
+                    Some(editor) => editor,
+                    None => return,
+                };
+                let action = lock_editor(&editor).auto_save();
+                match action {
+                    AutoSave::Wait(ms) => delay_ms = ms,
+                    AutoSave::Saved(path, result) => {
+                        rpc_peer.send_rpc_notification("auto_save",
+                            &ObjectBuilder::new()
+                                .insert("tab", &tab)
+                                .insert("path", path)
+                                .insert("time", ::time::get_time().sec)
+                                .insert("result", result)
+                                .unwrap());
+                        return;
+                    }
+                    AutoSave::Streaming(path, text, rev, ensure_final_newline) => {
+                        rpc_peer.send_rpc_notification("auto_save",
+                            &ObjectBuilder::new()
+                                .insert("tab", &tab)
+                                .insert("path", &path)
+                                .insert("time", ::time::get_time().sec)
+                                .insert("result", ObjectBuilder::new()
+                                    .insert("ok", true)
+                                    .insert("pending", true)
+                                    .unwrap())
+                                .unwrap());
+                        save_with_progress(&editor, &tab, &rpc_peer, &path, &text, rev,
+                            ensure_final_newline);
+                        return;
+                    }
+                    AutoSave::Cancelled => return,
+                }
+            }
+        });
+    }
+
+    /// Starts a thread that saves `text`, the text of revision `rev`, to
+    /// `path`, sending `save_progress` notifications as it goes and a
+    /// `save_done` notification with the result.
+    pub fn start_save(&self, path: String, text: Rope, rev: usize, ensure_final_newline: bool) {
+        let editor = self.self_ref.clone();
+        let tab = self.tab.clone();
+        let rpc_peer = self.rpc_peer.clone();
+        thread::spawn(move || {
+            save_with_progress(&editor, &tab, &rpc_peer, &path, &text, rev,
+                ensure_final_newline);
+        });
+    }
+
     pub fn get_kill_ring(&self) -> Rope {
         self.kill_ring.lock().unwrap().clone()
     }
@@ -127,6 +338,7 @@ impl TabCtx {
         PluginCtx {
             tab_ctx: self.clone(),
             rpc_peer: None,
+            priority: plugin_edit_priority(PLUGINS_STARTED.fetch_add(1, Ordering::Relaxed)),
         }
     }
 }
@@ -149,6 +361,20 @@ impl PluginCtx {
         self.tab_ctx.self_ref.lock().unwrap().plugin_get_line(line_num)
     }
 
+    // Makes an edit, with `priority` instead of the plugin's own if given,
+    // which has to be in the band for plugin edits.
+    pub fn edit(&self, rev: Option<usize>, start: usize, end: usize, text: &str,
+                priority: Option<usize>) -> bool {
+        let priority = priority.unwrap_or(self.priority);
+        if !is_plugin_edit_priority(priority) {
+            return false;
+        }
+        let mut editor = self.tab_ctx.self_ref.lock().unwrap();
+        let ok = editor.plugin_edit(rev, start, end, text, priority);
+        editor.render(&self.tab_ctx);
+        ok
+    }
+
     pub fn set_line_fg_spans(&self, line_num: usize, spans: &Value) {
         let mut editor = self.tab_ctx.self_ref.lock().unwrap();
         editor.plugin_set_line_fg_spans(line_num, spans);
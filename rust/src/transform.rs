@@ -0,0 +1,504 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pure text transformations used by editing commands.
+
+// The indent and list marker (if any) that start a paragraph, e.g. `"  - "`.
+fn paragraph_prefix(line: &str) -> &str {
+    let indent = line.bytes().take_while(|&b| b == b' ' || b == b'\t').count();
+    let rest = &line[indent..];
+    let marker_len = if rest.starts_with("- ") || rest.starts_with("* ") || rest.starts_with("+ ") {
+        2
+    } else {
+        let digits = rest.bytes().take_while(|b| b.is_ascii_digit()).count();
+        if digits > 0 && rest[digits..].starts_with(". ") { digits + 2 } else { 0 }
+    };
+    &line[..indent + marker_len]
+}
+
+/// Hard-wraps a paragraph so no line is longer than `width` characters, unless
+/// a single word is. The first line's indent and list marker are kept, and
+/// following lines are indented to line up with the text after them.
+pub fn reflow(paragraph: &str, width: usize) -> String {
+    let prefix = paragraph_prefix(paragraph);
+    let prefix_width = prefix.chars().count();
+    let cont_prefix: String = prefix.chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let mut result = String::from(prefix);
+    let mut line_width = prefix_width;
+    let mut at_line_start = true;
+    for word in paragraph[prefix.len()..].split_whitespace() {
+        let word_width = word.chars().count();
+        if !at_line_start && line_width + 1 + word_width > width {
+            result.push('\n');
+            result.push_str(&cont_prefix);
+            line_width = prefix_width;
+            at_line_start = true;
+        }
+        if !at_line_start {
+            result.push(' ');
+            line_width += 1;
+        }
+        result.push_str(word);
+        line_width += word_width;
+        at_line_start = false;
+    }
+    result
+}
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// Replaces tabs with spaces up to the next tab stop. With `leading_only`, only
+/// tabs in the indentation are replaced.
+pub fn expand_tabs(line: &str, tab_size: usize, leading_only: bool) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut col = 0;
+    let mut in_indent = true;
+    for c in line.chars() {
+        in_indent = in_indent && is_blank(c);
+        if c == '\t' && (in_indent || !leading_only) {
+            let n = tab_size - col % tab_size;
+            for _ in 0..n {
+                result.push(' ');
+            }
+            col += n;
+        } else {
+            result.push(c);
+            col += 1;
+        }
+    }
+    result
+}
+
+/// Removes `width` columns of indentation from `line`, with tab stops every
+/// `tab_size` columns; a tab reaching past `width` leaves the spaces beyond it.
+/// A blank line loses everything but its line ending.
+pub fn remove_indent(line: &str, width: usize, tab_size: usize) -> String {
+    let content = line.trim_start_matches(is_blank);
+    if content.trim_end_matches(&['\n', '\r'][..]).is_empty() {
+        return content.to_string();
+    }
+    let mut col = 0;
+    for (i, c) in line.char_indices() {
+        if col >= width || !is_blank(c) {
+            return line[i..].to_string();
+        }
+        let next = if c == '\t' { (col / tab_size + 1) * tab_size } else { col + 1 };
+        if next > width {
+            return " ".repeat(next - width) + &line[i + 1..];
+        }
+        col = next;
+    }
+    String::new()
+}
+
+// Appends whitespace covering columns `start..end`, using tabs where it reaches
+// a tab stop.
+fn push_tabbed_blanks(result: &mut String, start: usize, end: usize, tab_size: usize) {
+    let mut col = start;
+    loop {
+        let next_stop = (col / tab_size + 1) * tab_size;
+        if next_stop > end {
+            break;
+        }
+        result.push('\t');
+        col = next_stop;
+    }
+    for _ in col..end {
+        result.push(' ');
+    }
+}
+
+/// Replaces runs of blanks with tabs wherever they reach a tab stop. With
+/// `leading_only`, only the indentation is converted; otherwise single spaces
+/// between words are left alone.
+pub fn unexpand_spaces(line: &str, tab_size: usize, leading_only: bool) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut col = 0;
+    let mut run_start = None;
+    let mut run_len = 0;
+    let mut run_first = ' ';
+    let mut in_indent = true;
+    for c in line.chars() {
+        if is_blank(c) && (in_indent || !leading_only) {
+            if run_start.is_none() {
+                run_start = Some(col);
+                run_len = 0;
+                run_first = c;
+            }
+            col = if c == '\t' { (col / tab_size + 1) * tab_size } else { col + 1 };
+            run_len += 1;
+            continue;
+        }
+        if let Some(start) = run_start.take() {
+            if in_indent || run_len > 1 {
+                push_tabbed_blanks(&mut result, start, col, tab_size);
+            } else {
+                result.push(run_first);
+            }
+        }
+        in_indent = false;
+        result.push(c);
+        col += 1;
+    }
+    if let Some(start) = run_start {
+        push_tabbed_blanks(&mut result, start, col, tab_size);
+    }
+    result
+}
+
+fn is_single_word(s: &str) -> bool {
+    !s.is_empty() && !s.contains(char::is_whitespace)
+}
+
+/// Adapts `text`, pasted over `target`, to the case pattern of `target`: all
+/// capitals, or capitalized. Anything other than one word pasted over one word
+/// is returned as is.
+pub fn match_case(target: &str, text: &str) -> String {
+    if !is_single_word(target) || !is_single_word(text) {
+        return text.to_string();
+    }
+    let letters: Vec<char> = target.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.len() > 1 && letters.iter().all(|c| c.is_uppercase()) {
+        text.to_uppercase()
+    } else if !letters.is_empty() && letters[0].is_uppercase() &&
+        letters[1..].iter().all(|c| c.is_lowercase()) {
+        let mut chars = text.chars();
+        let first = chars.next().unwrap();
+        first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect()
+    } else {
+        text.to_string()
+    }
+}
+
+/// Swaps upper and lower case, one character at a time. Characters without
+/// case are left alone.
+pub fn toggle_case(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_uppercase() {
+            result.extend(c.to_lowercase());
+        } else if c.is_lowercase() {
+            result.extend(c.to_uppercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Escapes `s` for use inside a JSON string literal, without the quotes.
+pub fn escape_json(s: &str) -> String {
+    let quoted = ::serde_json::to_string(&s).unwrap();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// Reverses `escape_json`, failing on anything that isn't valid inside a JSON
+/// string literal.
+pub fn unescape_json(s: &str) -> Result<String, String> {
+    ::serde_json::from_str::<String>(&format!("\"{}\"", s))
+        .map_err(|e| format!("invalid JSON string: {}", e))
+}
+
+/// Percent-encodes everything in `s` but the characters URIs leave unreserved.
+pub fn escape_uri(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                result.push(b as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    result
+}
+
+/// Decodes percent-encoding, failing on a malformed escape or if the result
+/// isn't UTF-8.
+pub fn unescape_uri(s: &str) -> Result<String, String> {
+    let bytes = s.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| format!("malformed percent escape at {}", i))?;
+            result.push(hex);
+            i += 3;
+        } else {
+            result.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(result).map_err(|_| "percent escapes don't decode to UTF-8".to_string())
+}
+
+/// Shows the bytes of `s`, printable ASCII as is (but `\` doubled) and all
+/// others as `\x` escapes, such as `\xef\xbb\xbf` for a byte order mark.
+pub fn escape_bytes(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'\\' => result.push_str("\\\\"),
+            b' '..=b'~' => result.push(b as char),
+            _ => result.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    result
+}
+
+/// Escapes the characters that are special in HTML text and attributes.
+pub fn escape_html(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Decodes the entities `escape_html` produces, `&apos;`, `&nbsp;`, and
+/// numeric character references, failing on any other use of `&`.
+pub fn unescape_html(s: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        result.push_str(&rest[..amp]);
+        let entity_end = rest[amp..].find(';')
+            .ok_or_else(|| format!("unterminated entity: {}", &rest[amp..]))?;
+        let entity = &rest[amp + 1..amp + entity_end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                u32::from_str_radix(&entity[2..], 16).ok().and_then(::std::char::from_u32)
+            }
+            _ if entity.starts_with('#') => {
+                entity[1..].parse::<u32>().ok().and_then(::std::char::from_u32)
+            }
+            _ => None,
+        };
+        result.push(c.ok_or_else(|| format!("unknown entity: &{};", entity))?);
+        rest = &rest[amp + entity_end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Replaces curly quotes, en and em dashes, and ellipses with their ASCII
+/// equivalents (an em dash becomes `--`), returning the result and the number
+/// of characters replaced.
+pub fn straighten_quotes(s: &str) -> (String, usize) {
+    let mut result = String::with_capacity(s.len());
+    let mut count = 0;
+    for c in s.chars() {
+        let ascii = match c {
+            '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' => "'",
+            '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{201f}' => "\"",
+            '\u{2013}' => "-",
+            '\u{2014}' => "--",
+            '\u{2026}' => "...",
+            _ => {
+                result.push(c);
+                continue;
+            }
+        };
+        result.push_str(ascii);
+        count += 1;
+    }
+    (result, count)
+}
+
+// Whether a quote after `prev` opens a quotation rather than closing one (or
+// being an apostrophe); right after `open`, the same kind of quote closes it.
+fn opens_quote(prev: Option<char>, open: char) -> bool {
+    match prev {
+        None => true,
+        Some(c) if c == open => false,
+        Some(c) => c.is_whitespace() || "([{<\u{2018}\u{201c}\u{2013}\u{2014}".contains(c),
+    }
+}
+
+/// Replaces straight quotes with curly ones, `--` with an em dash, and `...`
+/// with an ellipsis, returning the result and the number of replacements. A
+/// quote at the start or after whitespace or an opening bracket opens; any
+/// other closes, as an apostrophe does. Longer runs of `-` or `.` are kept.
+pub fn smarten_quotes(s: &str) -> (String, usize) {
+    let mut result = String::with_capacity(s.len());
+    let mut count = 0;
+    let mut prev = None;
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        let (new, len) = match c {
+            '\'' => (if opens_quote(prev, '\u{2018}') { '\u{2018}' } else { '\u{2019}' }, 1),
+            '"' => (if opens_quote(prev, '\u{201c}') { '\u{201c}' } else { '\u{201d}' }, 1),
+            '-' if prev != Some('-') && rest.starts_with("--") && !rest.starts_with("---") => {
+                ('\u{2014}', 2)
+            }
+            '.' if prev != Some('.') && rest.starts_with("...") && !rest.starts_with("....") => {
+                ('\u{2026}', 3)
+            }
+            _ => (c, c.len_utf8()),
+        };
+        if new != c {
+            count += 1;
+        }
+        result.push(new);
+        prev = Some(new);
+        rest = &rest[len..];
+    }
+    (result, count)
+}
+
+/// Puts `text` in place of the first `$0` in `template`, or after the end of
+/// the template if it has none.
+pub fn fill_template(template: &str, text: &str) -> String {
+    match template.find("$0") {
+        Some(i) => format!("{}{}{}", &template[..i], text, &template[i + 2..]),
+        None => format!("{}{}", template, text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use transform::{reflow, expand_tabs, unexpand_spaces, remove_indent, match_case, toggle_case, escape_json,
+        unescape_json, escape_uri, unescape_uri, escape_html, unescape_html, fill_template,
+        escape_bytes, straighten_quotes, smarten_quotes};
+
+    #[test]
+    fn reflow_joins_and_splits() {
+        assert_eq!("aaa bbb\nccc ddd", reflow("aaa\nbbb ccc\nddd", 7));
+        assert_eq!("aaa bbb ccc ddd", reflow("aaa\nbbb ccc\nddd", 80));
+        assert_eq!("a\nlongword\nb", reflow("a longword b", 4));
+    }
+
+    #[test]
+    fn reflow_keeps_prefix() {
+        assert_eq!("  - aaa bbb\n    ccc", reflow("  - aaa\n  bbb ccc", 11));
+        assert_eq!("12. aaa\n    bbb", reflow("12. aaa bbb", 8));
+        assert_eq!("    aaa\n    bbb", reflow("    aaa bbb", 8));
+    }
+
+    #[test]
+    fn tabs_to_spaces() {
+        assert_eq!("        x\t\"a\tb\"\n", expand_tabs("\t  \tx\t\"a\tb\"\n", 4, true));
+        assert_eq!("        x   \"a  b\"\n", expand_tabs("\t  \tx\t\"a\tb\"\n", 4, false));
+    }
+
+    #[test]
+    fn spaces_to_tabs() {
+        assert_eq!("\t\t\t  x    y\n", unexpand_spaces("         \t  x    y\n", 4, true));
+        assert_eq!("\t  x", unexpand_spaces("  \t  x", 4, true));
+        assert_eq!("\t\tx\t \"a b\"\n", unexpand_spaces("      \tx    \"a b\"\n", 4, false));
+        assert_eq!("a\tb c", unexpand_spaces("a\tb c", 4, false));
+    }
+
+    #[test]
+    fn dedent() {
+        assert_eq!("  x\n", remove_indent("    x\n", 2, 4));
+        assert_eq!("  x", remove_indent("\tx", 2, 4));
+        assert_eq!("x", remove_indent(" \tx", 4, 4));
+        assert_eq!("\r\n", remove_indent("  \t \r\n", 2, 4));
+    }
+
+    #[test]
+    fn paste_match_case() {
+        assert_eq!("NEW_NAME", match_case("OLD_NAME", "new_name"));
+        assert_eq!("Widget", match_case("Gadget", "widget"));
+        assert_eq!("Widget", match_case("A", "wIDGET"));
+        assert_eq!("wIDGET", match_case("gadget", "wIDGET"));
+        assert_eq!("wIDGET", match_case("gAdget", "wIDGET"));
+        assert_eq!("two words", match_case("GADGET", "two words"));
+        assert_eq!("widget", match_case("TWO WORDS", "widget"));
+    }
+
+    #[test]
+    fn toggle_cases() {
+        assert_eq!("hELLO wORLD 42", toggle_case("Hello World 42"));
+        assert_eq!("\u{3a3}\u{3c3}SS\u{4e2d}", toggle_case("\u{3c3}\u{3a3}\u{df}\u{4e2d}"));
+    }
+
+    #[test]
+    fn json_escapes() {
+        assert_eq!("say \\\"hi\\\"\\n\\t\\\\", escape_json("say \"hi\"\n\t\\"));
+        assert_eq!(Ok("say \"hi\"\n\u{e9}".to_string()), unescape_json("say \\\"hi\\\"\\n\\u00e9"));
+        assert!(unescape_json("a\"b").is_err());
+        assert!(unescape_json("\\q").is_err());
+    }
+
+    #[test]
+    fn uri_escapes() {
+        assert_eq!("a%20b%2Fc~%C3%A9", escape_uri("a b/c~\u{e9}"));
+        assert_eq!(Ok("a b/c~\u{e9}".to_string()), unescape_uri("a%20b%2fc~%C3%A9"));
+        assert!(unescape_uri("100%").is_err());
+        assert!(unescape_uri("%zz").is_err());
+        assert!(unescape_uri("%FF").is_err());
+    }
+
+    #[test]
+    fn html_escapes() {
+        assert_eq!("&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s",
+            escape_html("<a href=\"x\">Tom & Jerry's"));
+        assert_eq!(Ok("<a> & \u{e9}\u{e9}'".to_string()),
+            unescape_html("&lt;a&gt; &amp; &#233;&#xE9;&apos;"));
+        assert!(unescape_html("fish & chips").is_err());
+        assert!(unescape_html("&bogus;").is_err());
+    }
+
+    #[test]
+    fn byte_escapes() {
+        assert_eq!("a\\\\b\\x0a\\xe2\\x80\\x8b~", escape_bytes("a\\b\n\u{200b}~"));
+    }
+
+    #[test]
+    fn straighten() {
+        assert_eq!(("\"'Tis\" -- 'a' ... it's-so".to_string(), 9),
+            straighten_quotes("\u{201c}\u{2018}Tis\u{201d} \u{2014} \u{2018}a\u{2019} \u{2026} \
+                               it\u{2019}s\u{2013}so"));
+        assert_eq!(("plain".to_string(), 0), straighten_quotes("plain"));
+    }
+
+    #[test]
+    fn smarten() {
+        assert_eq!(("a\u{2014}b\u{2026} --- .... \u{201c}\u{201d} \u{2018}\u{2019}".to_string(), 6),
+            smarten_quotes("a--b... --- .... \"\" ''"));
+        let expected = "\u{201c}\u{2018}hi\u{2019}\u{201d} (\u{201c}it\u{2019}s\u{201d})";
+        assert_eq!((expected.to_string(), 7),
+            smarten_quotes("\"'hi'\" (\"it's\")"));
+        let (smart, _) = smarten_quotes("\"'x' -- y...\"");
+        assert_eq!("\"'x' -- y...\"", straighten_quotes(&smart).0);
+    }
+
+    #[test]
+    fn templates() {
+        assert_eq!("<b>bold</b>", fill_template("<b>$0</b>", "bold"));
+        assert_eq!("f(x, $0)", fill_template("f($0, $0)", "x"));
+        assert_eq!("// x", fill_template("// ", "x"));
+    }
+}
@@ -24,7 +24,11 @@ use xi_rope::breaks::{Breaks, BreaksMetric, BreaksBaseMetric};
 use xi_rope::interval::Interval;
 use xi_rope::spans::{Spans, SpansBuilder};
 
+use xi_unicode::linebreak_property;
+
 use linewrap;
+use find::Find;
+use fold::Folds;
 
 const SCROLL_SLOP: usize = 2;
 
@@ -34,6 +38,25 @@ pub struct Style {
     pub font_style: u8,  // same as syntect, 1 = bold, 2 = underline, 4 = italic
 }
 
+/// How lines are soft-wrapped.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WrapMode {
+    /// Lines aren't wrapped.
+    NoWrap,
+    /// Lines are wrapped to the width of the window, as set by the front-end.
+    Window,
+    /// Lines are wrapped at a fixed column, whatever the width of the window.
+    Column(usize),
+}
+
+/// Where `recenter` puts the cursor's line in the window.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RecenterPosition {
+    Top,
+    Center,
+    Bottom,
+}
+
 pub struct View {
     pub sel_start: usize,
     pub sel_end: usize,
@@ -42,6 +65,16 @@ pub struct View {
     breaks: Option<Breaks>,
     style_spans: Spans<Style>,
     cols: usize,
+    hanging_indent: usize,  // extra indent for continuation rows when wrapped
+    wrap_mode: WrapMode,
+    window_cols: usize,  // width of the window in columns, 0 if unknown
+    find: Option<Find>,
+    folds: Folds,
+    // the block selection, as the selected range on each of its lines
+    block: Vec<(usize, usize)>,
+    seq: u64,  // sequence number of the last update rendered
+    show_trailing_whitespace: bool,
+    reveal_control_chars: bool,
 }
 
 impl Default for View {
@@ -54,6 +87,15 @@ impl Default for View {
             breaks: None,
             style_spans: Spans::default(),
             cols: 0,
+            hanging_indent: 0,
+            wrap_mode: WrapMode::NoWrap,
+            window_cols: 0,
+            find: None,
+            folds: Folds::new(),
+            block: Vec::new(),
+            seq: 0,
+            show_trailing_whitespace: false,
+            reveal_control_chars: false,
         }
     }
 }
@@ -63,9 +105,57 @@ impl View {
         View::default()
     }
 
-    pub fn set_scroll(&mut self, first: usize, last: usize) {
-        self.first_line = first;
-        self.height = last - first;
+    /// A view of a copy of `text` with the same selection, scroll position,
+    /// wrapping and markers, but no find highlights or styles.
+    pub fn duplicate(&self, text: &Rope) -> View {
+        let mut view = View {
+            sel_start: self.sel_start,
+            sel_end: self.sel_end,
+            first_line: self.first_line,
+            height: self.height,
+            hanging_indent: self.hanging_indent,
+            wrap_mode: self.wrap_mode,
+            window_cols: self.window_cols,
+            show_trailing_whitespace: self.show_trailing_whitespace,
+            reveal_control_chars: self.reveal_control_chars,
+            ..View::default()
+        };
+        view.update_wrap(text);
+        view
+    }
+
+    // Sets the visible region to the formatted lines `first..last`, swapped if
+    // reversed. The first line is clamped to the document, so the front-end
+    // can't put the viewport into a nonsensical state, but the height is the
+    // window's, at least 1, even where it reaches past the end of a short
+    // document, so that it stays right as the document grows.
+    pub fn set_scroll(&mut self, text: &Rope, first: usize, last: usize) {
+        let n_lines = self.line_of_offset(text, text.len()) + 1;
+        let (first, last) = (min(first, last), max(first, last));
+        self.first_line = min(first, n_lines);
+        self.height = max(last - first, 1);
+    }
+
+    /// The offsets of the start of the first visible line and the end of the
+    /// last (after its line break), clamped to the document.
+    pub fn visible_range(&self, text: &Rope) -> (usize, usize) {
+        let n_lines = self.line_of_offset(text, text.len()) + 1;
+        let offset_of = |line: usize| {
+            if line >= n_lines { text.len() } else { self.offset_of_line(text, line) }
+        };
+        (offset_of(self.first_line), offset_of(self.first_line + self.height))
+    }
+
+    /// Scrolls so that the cursor's line is at `position` in the window, as
+    /// far as the start of the document allows. Returns the new first line.
+    pub fn recenter(&mut self, text: &Rope, position: RecenterPosition) -> usize {
+        let (line, _) = self.offset_to_line_col(text, self.sel_end);
+        self.first_line = match position {
+            RecenterPosition::Top => line,
+            RecenterPosition::Center => line.saturating_sub(self.height / 2),
+            RecenterPosition::Bottom => line.saturating_sub(self.height - 1),
+        };
+        self.first_line
     }
 
     pub fn scroll_height(&self) -> usize {
@@ -89,7 +179,7 @@ impl View {
         }
     }
 
-    pub fn render_lines(&self, text: &Rope, first_line: usize, last_line: usize) -> Value {
+    pub fn render_lines(&self, text: &Rope, first_line: usize, mut last_line: usize) -> Value {
         let mut builder = ArrayBuilder::new();
         let (cursor_line, cursor_col) = self.offset_to_line_col(text, self.sel_end);
         let sel_min_line = if self.sel_start == self.sel_end {
@@ -129,6 +219,15 @@ impl View {
                     text.len()
                 }
             };
+            if self.folds.hidden_at(start_pos).is_some() {
+                // hidden lines don't count toward the lines rendered
+                line_num += 1;
+                last_line = last_line.saturating_add(1);
+                if is_last_line {
+                    break;
+                }
+                continue;
+            }
             let l_str = text.slice_to_string(start_pos, pos);
             let l = &l_str;
             // TODO: strip trailing line end
@@ -152,6 +251,72 @@ impl View {
                         .push(sel_end_ix)
                 );
             }
+            for &(b_start, b_end) in &self.block {
+                let in_row = if b_start == b_end {
+                    b_start >= start_pos && (b_start < pos || is_last_line)
+                } else {
+                    b_end > start_pos && b_start < pos
+                };
+                if in_row {
+                    let b_start = max(b_start, start_pos) - start_pos;
+                    let b_end = min(b_end, pos) - start_pos;
+                    line_builder = line_builder.push_array(|builder|
+                        builder.push("block")
+                            .push(b_start)
+                            .push(b_end)
+                    );
+                }
+            }
+            if let Some(ref find) = self.find {
+                for &(m_start, m_end) in find.matches() {
+                    if m_end > start_pos && m_start < pos {
+                        let m_start = max(m_start, start_pos) - start_pos;
+                        let m_end = min(m_end, pos) - start_pos;
+                        line_builder = line_builder.push_array(|builder|
+                            builder.push("find")
+                                .push(m_start)
+                                .push(m_end)
+                        );
+                    }
+                }
+            }
+            if self.show_trailing_whitespace {
+                if let Some((ws_start, ws_end)) = trailing_whitespace(text, start_pos) {
+                    if ws_end > start_pos && ws_start < pos {
+                        let ws_start = max(ws_start, start_pos) - start_pos;
+                        let ws_end = min(ws_end, pos) - start_pos;
+                        line_builder = line_builder.push_array(|builder|
+                            builder.push("trailing_ws")
+                                .push(ws_start)
+                                .push(ws_end)
+                        );
+                    }
+                }
+            }
+            if self.reveal_control_chars {
+                for (c_start, c_end) in invisible_chars(l) {
+                    line_builder = line_builder.push_array(|builder|
+                        builder.push("control")
+                            .push(c_start)
+                            .push(c_end)
+                    );
+                }
+            }
+            if self.breaks.is_some() {
+                let indent = linewrap::row_indent(text, start_pos, self.cols, self.hanging_indent);
+                if indent > 0 {
+                    line_builder = line_builder.push_array(|builder|
+                        builder.push("indent")
+                            .push(indent)
+                    );
+                }
+            }
+            if let Some((hidden_start, hidden_end)) = self.folds.hidden_after(pos) {
+                line_builder = line_builder.push_array(|builder|
+                    builder.push("fold")
+                        .push(count_lines(text, hidden_start, hidden_end))
+                );
+            }
             if line_num == cursor_line {
                 line_builder = line_builder.push_array(|builder|
                     builder.push("cursor")
@@ -179,7 +344,9 @@ impl View {
         builder
     }
 
-    pub fn render(&self, text: &Rope, scroll_to: Option<usize>) -> Value {
+    pub fn render(&mut self, text: &Rope, rev: usize, scroll_to: Option<usize>,
+                  scroll_top: Option<usize>, tab_size: usize, overwrite: bool) -> Value {
+        self.seq += 1;
         let first_line = max(self.first_line, SCROLL_SLOP) - SCROLL_SLOP;
         let last_line = self.first_line + self.height + SCROLL_SLOP;
         let lines = self.render_lines(text, first_line, last_line);
@@ -187,12 +354,19 @@ impl View {
         let mut builder = ObjectBuilder::new()
             .insert("lines", lines)
             .insert("first_line", first_line)
-            .insert("height", height);
+            .insert("height", height)
+            .insert("rev", rev)
+            .insert("seq", self.seq)
+            .insert("overwrite", overwrite);
+        builder = builder.insert("cursor", self.cursor_position(text, tab_size));
         if let Some(scrollto) = scroll_to {
             let (line, col) = self.offset_to_line_col(text, scrollto);
             builder = builder.insert_array("scrollto", |builder|
                 builder.push(line).push(col));
         }
+        if let Some(line) = scroll_top {
+            builder = builder.insert("scrolltop", line);
+        }
         builder.unwrap()
     }
 
@@ -206,11 +380,35 @@ impl View {
     // Of course, all these are identical for ASCII. For now we use UTF-8 code units
     // for simplicity.
 
+    // The cursor position on its logical line, for status displays: the line,
+    // the column in grapheme clusters, and the display column.
+    fn cursor_position(&self, text: &Rope, tab_size: usize) -> Value {
+        let line = text.line_of_offset(self.sel_end);
+        let line_start = text.offset_of_line(line);
+        let mut graphemes = 0;
+        let mut offset = line_start;
+        while offset < self.sel_end {
+            offset = text.next_grapheme_offset(offset).unwrap_or(self.sel_end);
+            graphemes += 1;
+        }
+        let prefix = text.slice_to_string(line_start, self.sel_end);
+        ObjectBuilder::new()
+            .insert("line", line)
+            .insert("col", graphemes)
+            .insert("display_col", display_width(&prefix, 0, tab_size))
+            .unwrap()
+    }
+
+    /// The visual line (formatted row, counting wrapped rows) of `offset`, and
+    /// its column on that row. This is what the front-end draws, so it is used
+    /// for clicks, drags and moving up and down.
     pub fn offset_to_line_col(&self, text: &Rope, offset: usize) -> (usize, usize) {
         let line = self.line_of_offset(text, offset);
         (line, offset - self.offset_of_line(text, line))
     }
 
+    /// The offset of a column on a visual line (see `offset_to_line_col`),
+    /// clamped to the row and snapped to a grapheme boundary.
     pub fn line_col_to_offset(&self, text: &Rope, line: usize, col: usize) -> usize {
         let mut offset = self.offset_of_line(text, line).saturating_add(col);
         if offset >= text.len() {
@@ -233,12 +431,43 @@ impl View {
         offset
     }
 
+    /// The logical line (ignoring wrapping) of `offset`, and its column in UTF-8
+    /// code units from the start of that line, as with positions in files.
+    pub fn offset_to_logical_line_col(&self, text: &Rope, offset: usize) -> (usize, usize) {
+        let line = text.line_of_offset(offset);
+        (line, offset - text.offset_of_line(line))
+    }
+
+    /// The offset of a column on a logical line (see
+    /// `offset_to_logical_line_col`), clamped to the document and to the end of
+    /// the line before its line ending, and snapped to a grapheme boundary.
+    pub fn logical_line_col_to_offset(&self, text: &Rope, line: usize, col: usize) -> usize {
+        let line = min(line, text.line_of_offset(text.len()));
+        let line_start = text.offset_of_line(line);
+        let line_string = text.slice_to_string(line_start, text.offset_of_line(line + 1));
+        let content_len = line_string.trim_end_matches(&['\n', '\r'][..]).len();
+        let offset = line_start + min(col, content_len);
+        if offset >= text.len() {
+            text.len()
+        } else {
+            text.prev_grapheme_offset(offset + 1).unwrap()
+        }
+    }
+
     // Move up or down by `line_delta` lines and return offset where the
     // cursor lands. The `col` argument should probably move into the View
     // struct.
+    //
+    // Moving up past the first line lands at offset 0, and moving down past
+    // the last line lands at the end of the document. Callers shouldn't treat
+    // these as hard moves, so that the goal column is kept.
+    //
+    // Lines hidden by folds are stepped over, as they aren't drawn.
     pub fn vertical_motion(&self, text: &Rope, line_delta: isize, col: usize) -> usize {
+        if !self.folds.is_empty() {
+            return self.vertical_motion_folded(text, line_delta, col);
+        }
         // This code is quite careful to avoid integer overflow.
-        // TODO: write tests to verify
         let line = self.line_of_offset(text, self.sel_end);
         if line_delta < 0 && (-line_delta as usize) > line {
             return 0;
@@ -255,6 +484,38 @@ impl View {
         self.line_col_to_offset(text, line, col)
     }
 
+    // Like vertical_motion, a line at a time so that hidden lines can be
+    // skipped. Moving down past the last visible line lands at its end.
+    fn vertical_motion_folded(&self, text: &Rope, line_delta: isize, col: usize) -> usize {
+        let n_lines = self.line_of_offset(text, text.len());
+        let mut line = self.line_of_offset(text, self.sel_end);
+        for _ in 0..line_delta.unsigned_abs() {
+            if line_delta < 0 {
+                if line == 0 {
+                    return 0;
+                }
+                line -= 1;
+                // the line before a fold is its placeholder, so it is visible
+                while let Some((start, _)) = self.folds.hidden_at(self.offset_of_line(text, line)) {
+                    line = self.line_of_offset(text, start).saturating_sub(1);
+                }
+            } else {
+                let mut next = line + 1;
+                while next <= n_lines {
+                    match self.folds.hidden_at(self.offset_of_line(text, next)) {
+                        Some((_, end)) => next = max(next + 1, self.line_of_offset(text, end)),
+                        None => break,
+                    }
+                }
+                if next > n_lines {
+                    return self.line_col_to_offset(text, line, usize::MAX);
+                }
+                line = next;
+            }
+        }
+        self.line_col_to_offset(text, line, col)
+    }
+
     // use own breaks if present, or text if not (no line wrapping)
 
     fn line_of_offset(&self, text: &Rope, offset: usize) -> usize {
@@ -276,10 +537,58 @@ impl View {
     }
 
     pub fn rewrap(&mut self, text: &Rope, cols: usize) {
-        self.breaks = Some(linewrap::linewrap(text, cols));
+        self.breaks = Some(linewrap::linewrap(text, cols, self.hanging_indent));
         self.cols = cols;
     }
 
+    pub fn set_wrap_mode(&mut self, text: &Rope, wrap_mode: WrapMode) {
+        self.wrap_mode = wrap_mode;
+        self.update_wrap(text);
+    }
+
+    /// Records the width of the window, rewrapping if lines are wrapped to it.
+    pub fn set_window_width(&mut self, text: &Rope, cols: usize) {
+        self.window_cols = cols;
+        if self.wrap_mode == WrapMode::Window {
+            self.update_wrap(text);
+        }
+    }
+
+    pub fn wrap_mode(&self) -> WrapMode {
+        self.wrap_mode
+    }
+
+    /// The column lines are wrapped at under the current mode, if any.
+    pub fn wrap_cols(&self) -> Option<usize> {
+        match self.wrap_mode {
+            WrapMode::NoWrap => None,
+            WrapMode::Window if self.window_cols == 0 => None,
+            WrapMode::Window => Some(self.window_cols),
+            WrapMode::Column(cols) => Some(cols),
+        }
+    }
+
+    // Brings the breaks in line with the wrap mode, rewrapping only if the
+    // wrap column has changed.
+    fn update_wrap(&mut self, text: &Rope) {
+        match self.wrap_cols() {
+            Some(cols) => if self.breaks.is_none() || cols != self.cols {
+                self.rewrap(text, cols);
+            },
+            None => self.breaks = None,
+        }
+    }
+
+    /// Sets the indent continuation rows get in addition to their logical
+    /// line's leading whitespace, rewrapping if wrapping is on.
+    pub fn set_hanging_indent(&mut self, text: &Rope, hanging_indent: usize) {
+        self.hanging_indent = hanging_indent;
+        if self.breaks.is_some() {
+            let cols = self.cols;
+            self.rewrap(text, cols);
+        }
+    }
+
     pub fn after_edit(&mut self, text: &Rope, delta: &Delta<RopeInfo>) {
         let (iv, new_len) = delta.summary();
         // Note: this logic almost replaces setting the cursor in Editor::commit_delta,
@@ -296,7 +605,8 @@ impl View {
             self.sel_start = self.sel_end;
         }
         if self.breaks.is_some() {
-            linewrap::rewrap(self.breaks.as_mut().unwrap(), text, iv, new_len, self.cols);
+            linewrap::rewrap(self.breaks.as_mut().unwrap(), text, iv, new_len, self.cols,
+                self.hanging_indent);
         }
         // TODO: maybe more precise editing based on actual delta rather than summary.
         // TODO: perhaps use different semantics for spans that enclose the edited region.
@@ -304,6 +614,84 @@ impl View {
         // text. That's ok for syntax highlighting but not ideal for rich text.
         let empty_spans = SpansBuilder::new(new_len).build();
         self.style_spans.edit(iv, empty_spans);
+        if let Some(ref mut find) = self.find {
+            find.after_edit(iv, new_len);
+        }
+        self.folds.after_edit(iv, new_len);
+        self.block.clear();
+    }
+
+    /// Updates the view for entirely new text, as when a file is opened. The
+    /// wrap settings are kept, and the breaks recomputed for the new text.
+    pub fn after_reset(&mut self, text: &Rope) {
+        self.reset_breaks();
+        self.update_wrap(text);
+        self.folds.clear();
+        self.block.clear();
+        if let Some(ref mut find) = self.find {
+            find.search_all(text);
+        }
+    }
+
+    /// Sets whether rendered lines mark their trailing whitespace.
+    pub fn set_show_trailing_whitespace(&mut self, show: bool) {
+        self.show_trailing_whitespace = show;
+    }
+
+    /// Switches whether rendered lines mark control characters and other
+    /// invisible codepoints, returning whether they now do.
+    pub fn toggle_reveal_control_chars(&mut self) -> bool {
+        self.reveal_control_chars = !self.reveal_control_chars;
+        self.reveal_control_chars
+    }
+
+    /// Sets the block selection to the given range on each of its lines, or
+    /// clears it if `segments` is empty. Any edit clears it.
+    pub fn set_block(&mut self, segments: Vec<(usize, usize)>) {
+        self.block = segments;
+    }
+
+    pub fn block(&self) -> &[(usize, usize)] {
+        &self.block
+    }
+
+    /// Hides the lines in `start..end`, which should start and end at line
+    /// boundaries, behind the line before them.
+    pub fn add_fold(&mut self, start: usize, end: usize) {
+        self.folds.add(start, end);
+    }
+
+    /// Unhides the fold hidden behind, or containing, the logical line
+    /// starting at `line_start` and ending at `line_end`.
+    pub fn remove_fold(&mut self, line_start: usize, line_end: usize) -> bool {
+        self.folds.remove_at(line_start, line_end)
+    }
+
+    /// Highlights the matches of `query`, or stops highlighting if it is empty.
+    /// Returns the number of matches.
+    pub fn set_find(&mut self, text: &Rope, query: &str, case_sensitive: bool, whole_word: bool)
+            -> usize {
+        if query.is_empty() {
+            self.find = None;
+            0
+        } else {
+            let find = Find::new(text, query, case_sensitive, whole_word);
+            let n_matches = find.matches().len();
+            self.find = Some(find);
+            n_matches
+        }
+    }
+
+    /// Brings the find matches up to date after edits; needed before rendering.
+    pub fn update_find(&mut self, text: &Rope) {
+        if let Some(ref mut find) = self.find {
+            find.update(text);
+        }
+    }
+
+    /// The number of soft line breaks stored for wrapping.
+    pub fn n_breaks(&self) -> usize {
+        self.breaks.as_ref().map_or(0, |breaks| breaks.measure::<BreaksMetric>())
     }
 
     pub fn reset_breaks(&mut self) {
@@ -321,3 +709,256 @@ impl View {
         self.style_spans.edit(Interval::new_closed_closed(start, end), spans);
     }
 }
+
+// Line breaking classes (see xi-unicode's mk_tables.py) of characters that
+// don't take up a column, and of those that take two: ideographs, Hangul
+// syllables and jamo, and small kana. This approximates the East Asian Width
+// property, which xi-unicode doesn't have.
+const LB_CM: u8 = 9;
+const LB_ZW: u8 = 28;
+const LB_WIDE: [u8; 7] = [14, 31, 32, 33, 34, 35, 37];
+
+/// The number of columns a character takes up when displayed.
+pub fn char_width(c: char) -> usize {
+    let lb = linebreak_property(c);
+    if lb == LB_CM || lb == LB_ZW {
+        0
+    } else if LB_WIDE.contains(&lb) {
+        2
+    } else {
+        1
+    }
+}
+
+/// The display column reached by `s` when it starts at display column `col`,
+/// with tab stops every `tab_size` columns.
+pub fn display_width(s: &str, col: usize, tab_size: usize) -> usize {
+    s.chars().fold(col, |col, c| match c {
+        '\t' => (col / tab_size + 1) * tab_size,
+        '\n' | '\r' => col,
+        _ => col + char_width(c),
+    })
+}
+
+// The spaces and tabs at the end of the logical line containing `offset`,
+// before its line ending, if there are any.
+fn trailing_whitespace(text: &Rope, offset: usize) -> Option<(usize, usize)> {
+    let line_num = text.line_of_offset(offset);
+    let line_start = text.offset_of_line(line_num);
+    let line = text.slice_to_string(line_start, text.offset_of_line(line_num + 1));
+    let content = line.trim_end_matches(&['\n', '\r'][..]);
+    let trimmed = content.trim_end_matches(&[' ', '\t'][..]);
+    if trimmed.len() < content.len() {
+        Some((line_start + trimmed.len(), line_start + content.len()))
+    } else {
+        None
+    }
+}
+
+// Whether `c` is a control character other than a tab or newline, or a
+// codepoint rendered as nothing, such as a zero-width space or byte order mark.
+fn is_invisible(c: char) -> bool {
+    match c {
+        '\t' | '\n' => false,
+        '\u{ad}' | '\u{34f}' | '\u{61c}' | '\u{180e}' | '\u{200b}'..='\u{200f}' |
+        '\u{202a}'..='\u{202e}' | '\u{2060}'..='\u{2064}' | '\u{2066}'..='\u{206f}' |
+        '\u{feff}' => true,
+        _ => c.is_control(),
+    }
+}
+
+// The byte ranges of the invisible characters in a row, not counting a `\r`
+// that is part of its line ending.
+fn invisible_chars(row: &str) -> Vec<(usize, usize)> {
+    let content = row.strip_suffix("\r\n").unwrap_or(row);
+    content.char_indices()
+        .filter(|&(_, c)| is_invisible(c))
+        .map(|(i, c)| (i, i + c.len_utf8()))
+        .collect()
+}
+
+/// The number of logical lines starting in `start..end`.
+pub fn count_lines(text: &Rope, start: usize, end: usize) -> usize {
+    if start >= end {
+        return 0;
+    }
+    let n = text.line_of_offset(end) - text.line_of_offset(start);
+    if end == text.len() && text.byte_at(end - 1) != b'\n' { n + 1 } else { n }
+}
+
+#[cfg(test)]
+mod tests {
+    use xi_rope::rope::Rope;
+    use serde_json::Value;
+    use serde_json::to_string;
+    use view::{RecenterPosition, View, WrapMode, display_width};
+
+    fn view_at(offset: usize) -> View {
+        let mut view = View::new();
+        view.sel_start = offset;
+        view.sel_end = offset;
+        view
+    }
+
+    #[test]
+    fn set_scroll() {
+        let text = Rope::from("a\nb\nc\nd");
+        let mut view = View::new();
+        view.set_scroll(&text, 1, 3);
+        assert_eq!((1, 2), (view.first_line, view.height));
+        view.set_scroll(&text, 3, 1);
+        assert_eq!((1, 2), (view.first_line, view.height));
+        view.set_scroll(&text, 2, 100);
+        assert_eq!((2, 98), (view.first_line, view.height));
+        view.set_scroll(&text, 100, 200);
+        assert_eq!((4, 100), (view.first_line, view.height));
+        view.set_scroll(&text, 2, 2);
+        assert_eq!((2, 1), (view.first_line, view.height));
+    }
+
+    #[test]
+    fn visible_range() {
+        let text = Rope::from("ab\ncd\nef\ngh");
+        let mut view = View::new();
+        view.set_scroll(&text, 1, 3);
+        assert_eq!((3, 9), view.visible_range(&text));
+        view.set_scroll(&text, 2, 100);
+        assert_eq!((6, 11), view.visible_range(&text));
+        view.set_scroll(&text, 100, 200);
+        assert_eq!((11, 11), view.visible_range(&text));
+    }
+
+    #[test]
+    fn reset_keeps_wrap() {
+        let mut view = View::new();
+        view.set_wrap_mode(&Rope::from(""), WrapMode::Column(10));
+        let text = Rope::from("aaaa bbbb cccc dddd");
+        view.after_reset(&text);
+        assert_eq!(Some(10), view.wrap_cols());
+        assert_eq!((1, 4), view.offset_to_line_col(&text, 14));
+    }
+
+    #[test]
+    fn logical_and_visual_lines() {
+        let mut view = View::new();
+        let text = Rope::from("aaaa bbbb cccc\ndd");
+        view.set_wrap_mode(&text, WrapMode::Column(10));
+        // "cccc" is on the second row of the first logical line
+        assert_eq!((1, 2), view.offset_to_line_col(&text, 12));
+        assert_eq!((0, 12), view.offset_to_logical_line_col(&text, 12));
+        assert_eq!(12, view.line_col_to_offset(&text, 1, 2));
+        assert_eq!(12, view.logical_line_col_to_offset(&text, 0, 12));
+        // "dd" is the third row but the second logical line
+        assert_eq!((2, 1), view.offset_to_line_col(&text, 16));
+        assert_eq!((1, 1), view.offset_to_logical_line_col(&text, 16));
+        assert_eq!(16, view.line_col_to_offset(&text, 2, 1));
+        assert_eq!(16, view.logical_line_col_to_offset(&text, 1, 1));
+        // positions past the end clamp to the last line, and to before the line
+        // ending
+        assert_eq!(14, view.logical_line_col_to_offset(&text, 0, 100));
+        assert_eq!(15, view.logical_line_col_to_offset(&text, 5, 0));
+    }
+
+    #[test]
+    fn trailing_whitespace_markers() {
+        let text = Rope::from("a  \nb\n \t\r\nc d ");
+        let mut view = View::new();
+        view.set_show_trailing_whitespace(true);
+        assert_eq!(r#"[["a  \n",["trailing_ws",1,3],["cursor",0]],["b\n"],[" \t\r\n",["trailing_ws",0,2]],["c d ",["trailing_ws",3,4]]]"#,
+                   to_string(&view.render_lines(&text, 0, 4)).unwrap());
+        view.set_show_trailing_whitespace(false);
+        assert_eq!(r#"[["a  \n",["cursor",0]],["b\n"],[" \t\r\n"],["c d "]]"#,
+                   to_string(&view.render_lines(&text, 0, 4)).unwrap());
+    }
+
+    #[test]
+    fn control_char_markers() {
+        let text = Rope::from("a\u{200b}b\r\n\u{feff}\tc\u{7}\r");
+        let mut view = View::new();
+        assert!(view.toggle_reveal_control_chars());
+        assert_eq!(format!(r#"[["a{}b\r\n",["control",1,4],["cursor",0]],["{}\tc\u0007\r",["control",0,3],["control",5,6],["control",6,7]]]"#,
+                               '\u{200b}', '\u{feff}'),
+                   to_string(&view.render_lines(&text, 0, 2)).unwrap());
+        assert!(!view.toggle_reveal_control_chars());
+    }
+
+    #[test]
+    fn recenter() {
+        let text = Rope::from("0\n1\n2\n3\n4\n5\n6\n7\n8\n9");
+        let mut view = view_at(10);
+        view.set_scroll(&text, 0, 4);
+        assert_eq!(5, view.recenter(&text, RecenterPosition::Top));
+        assert_eq!(3, view.recenter(&text, RecenterPosition::Center));
+        assert_eq!(2, view.recenter(&text, RecenterPosition::Bottom));
+        assert_eq!(0, view_at(2).recenter(&text, RecenterPosition::Bottom));
+    }
+
+    #[test]
+    fn render_seq() {
+        let text = Rope::from("ab");
+        let mut view = view_at(0);
+        assert_eq!(Some(1), view.render(&text, 0, None, None, 4, false).lookup("seq")
+                   .and_then(Value::as_u64));
+        assert_eq!(Some(2), view.render(&text, 0, None, None, 4, false).lookup("seq")
+                   .and_then(Value::as_u64));
+    }
+
+    #[test]
+    fn vertical_motion() {
+        let text = Rope::from("abc\ndefgh\nij");
+        let view = view_at(5);
+        assert_eq!(1, view.vertical_motion(&text, -1, 1));
+        assert_eq!(10, view.vertical_motion(&text, 1, 0));
+        // goal column past the end of the target line clamps to it
+        assert_eq!(12, view.vertical_motion(&text, 1, 4));
+        assert_eq!(3, view.vertical_motion(&text, -1, 4));
+    }
+
+    #[test]
+    fn vertical_motion_edges() {
+        let text = Rope::from("abc\ndefgh\nij");
+        assert_eq!(0, view_at(2).vertical_motion(&text, -1, 2));
+        assert_eq!(0, view_at(5).vertical_motion(&text, -2, 3));
+        assert_eq!(0, view_at(5).vertical_motion(&text, -1000, 3));
+        assert_eq!(12, view_at(11).vertical_motion(&text, 1, 0));
+        assert_eq!(12, view_at(5).vertical_motion(&text, 1000, 0));
+        assert_eq!(12, view_at(5).vertical_motion(&text, isize::MAX, 0));
+
+        let empty = Rope::from("");
+        assert_eq!(0, view_at(0).vertical_motion(&empty, -1, 0));
+        assert_eq!(0, view_at(0).vertical_motion(&empty, 1, 0));
+    }
+
+    #[test]
+    fn vertical_motion_over_fold() {
+        let text = Rope::from("a\nbb\ncc\nd\ne\nf");
+        let mut view = view_at(0);
+        // lines 1 and 2 are hidden behind line 0
+        view.add_fold(2, 8);
+        assert_eq!(8, view.vertical_motion(&text, 1, 0));
+        assert_eq!(10, view.vertical_motion(&text, 2, 0));
+        view.sel_end = 10;
+        assert_eq!(1, view.vertical_motion(&text, -2, 1));
+        assert_eq!(0, view.vertical_motion(&text, -10, 1));
+        assert_eq!(13, view.vertical_motion(&text, isize::MAX, 0));
+        // the hidden lines don't count toward the lines rendered
+        let lines = view.render_lines(&text, 0, 3);
+        assert_eq!(vec!["a\n", "d\n", "e\n"], lines.as_array().unwrap().iter()
+            .map(|line| line.as_array().unwrap()[0].as_string().unwrap())
+            .collect::<Vec<_>>());
+        // with nothing visible below, the cursor stays on the last visible line
+        view.add_fold(10, 13);
+        view.sel_end = 8;
+        assert_eq!(9, view.vertical_motion(&text, 1, 0));
+    }
+
+    #[test]
+    fn display_widths() {
+        assert_eq!(3, display_width("abc", 0, 4));
+        assert_eq!(9, display_width("a\tb\tc", 0, 4));
+        assert_eq!(9, display_width("\tx", 6, 4));
+        // wide ideographs, and a combining accent
+        assert_eq!(5, display_width("\u{4e2d}\u{6587}e\u{301}", 0, 4));
+        assert_eq!(2, display_width("\u{d55c}\n", 0, 4));
+    }
+}
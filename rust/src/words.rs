@@ -0,0 +1,246 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Word and subword boundaries, for word-wise motion and deletion.
+
+use xi_rope::rope::Rope;
+use xi_rope::tree::Cursor;
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum CharClass {
+    Space,
+    Punctuation,
+    Underscore,
+    Upper,
+    // lowercase, digits, and letters without case
+    Lower,
+}
+
+use self::CharClass::*;
+
+fn classify(c: char) -> CharClass {
+    if c.is_whitespace() {
+        Space
+    } else if c == '_' {
+        Underscore
+    } else if c.is_uppercase() {
+        Upper
+    } else if c.is_alphanumeric() {
+        Lower
+    } else {
+        Punctuation
+    }
+}
+
+fn is_word(class: CharClass) -> bool {
+    class != Space && class != Punctuation
+}
+
+/// Whether `c` is part of a word, for whole-word matching.
+pub fn is_word_char(c: char) -> bool {
+    is_word(classify(c))
+}
+
+/// Counts the words in `s`, for statistics: runs of non-whitespace that contain
+/// at least one letter or digit, so that punctuation like `--` isn't counted.
+pub fn count_words(s: &str) -> usize {
+    s.split(char::is_whitespace)
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+/// A cursor for finding word boundaries. In subword mode, identifiers are
+/// further split at underscores and at case transitions, so that `foo_bar`,
+/// `fooBar` and `FOO_BAR` each contain two subwords.
+pub struct WordCursor<'a> {
+    text: &'a Rope,
+    pos: usize,
+}
+
+impl<'a> WordCursor<'a> {
+    pub fn new(text: &'a Rope, pos: usize) -> WordCursor<'a> {
+        WordCursor {
+            text: text,
+            pos: pos,
+        }
+    }
+
+    fn peek_prev(&self) -> Option<CharClass> {
+        self.text.prev_codepoint_offset(self.pos).map(|prev| {
+            let s = self.text.slice_to_string(prev, self.pos);
+            classify(s.chars().next().unwrap())
+        })
+    }
+
+    fn peek_next(&self) -> Option<CharClass> {
+        Cursor::new(self.text, self.pos).next_codepoint().map(classify)
+    }
+
+    fn skip_prev<F: Fn(CharClass) -> bool>(&mut self, f: F) -> usize {
+        let mut n = 0;
+        while let Some(class) = self.peek_prev() {
+            if !f(class) {
+                break;
+            }
+            self.pos = self.text.prev_codepoint_offset(self.pos).unwrap();
+            n += 1;
+        }
+        n
+    }
+
+    fn skip_next<F: Fn(CharClass) -> bool>(&mut self, f: F) -> usize {
+        let mut n = 0;
+        while let Some(class) = self.peek_next() {
+            if !f(class) {
+                break;
+            }
+            self.pos = self.text.next_codepoint_offset(self.pos).unwrap();
+            n += 1;
+        }
+        n
+    }
+
+    /// Finds the start of the word the cursor is in or just after.
+    pub fn word_start(&mut self) -> usize {
+        self.skip_prev(is_word);
+        self.pos
+    }
+
+    /// Finds the end of the word the cursor is in or just before.
+    pub fn word_end(&mut self) -> usize {
+        self.skip_next(is_word);
+        self.pos
+    }
+
+    /// Finds the start of the word (or subword) before the cursor, skipping
+    /// any whitespace in between.
+    pub fn prev_boundary(&mut self, subword: bool) -> usize {
+        self.skip_prev(|c| c == Space);
+        match self.peek_prev() {
+            Some(Punctuation) => {
+                self.skip_prev(|c| c == Punctuation);
+            }
+            Some(_) if !subword => {
+                self.skip_prev(is_word);
+            }
+            Some(_) => {
+                self.skip_prev(|c| c == Underscore);
+                if self.skip_prev(|c| c == Lower) > 0 {
+                    // the capital starting a camelCase hump belongs to it
+                    if self.peek_prev() == Some(Upper) {
+                        self.pos = self.text.prev_codepoint_offset(self.pos).unwrap();
+                    }
+                } else {
+                    self.skip_prev(|c| c == Upper);
+                }
+            }
+            None => (),
+        }
+        self.pos
+    }
+
+    /// Finds the end of the word (or subword) after the cursor, skipping
+    /// any whitespace in between.
+    pub fn next_boundary(&mut self, subword: bool) -> usize {
+        self.skip_next(|c| c == Space);
+        match self.peek_next() {
+            Some(Punctuation) => {
+                self.skip_next(|c| c == Punctuation);
+            }
+            Some(_) if !subword => {
+                self.skip_next(is_word);
+            }
+            Some(_) => {
+                self.skip_next(|c| c == Underscore);
+                let start = self.pos;
+                let n_upper = self.skip_next(|c| c == Upper);
+                if n_upper > 1 && self.peek_next() == Some(Lower) {
+                    // in `HTTPServer`, the `S` starts the next subword
+                    self.pos = self.text.prev_codepoint_offset(self.pos).unwrap();
+                } else if n_upper <= 1 {
+                    self.skip_next(|c| c == Lower);
+                }
+                if self.pos == start {
+                    // only underscores
+                    self.skip_next(|c| c == Underscore);
+                }
+            }
+            None => (),
+        }
+        self.pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xi_rope::rope::Rope;
+    use words::{WordCursor, count_words};
+
+    fn prev_boundaries(s: &str, subword: bool) -> Vec<usize> {
+        let text = Rope::from(s);
+        let mut result = Vec::new();
+        let mut pos = text.len();
+        while pos > 0 {
+            pos = WordCursor::new(&text, pos).prev_boundary(subword);
+            result.push(pos);
+        }
+        result
+    }
+
+    fn next_boundaries(s: &str, subword: bool) -> Vec<usize> {
+        let text = Rope::from(s);
+        let mut result = Vec::new();
+        let mut pos = 0;
+        while pos < text.len() {
+            pos = WordCursor::new(&text, pos).next_boundary(subword);
+            result.push(pos);
+        }
+        result
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(vec![12, 10, 9, 0], prev_boundaries("foo_bar  x.(yz", false));
+        assert_eq!(vec![7, 10, 12, 14], next_boundaries("foo_bar  x.(yz", false));
+    }
+
+    #[test]
+    fn snake_case() {
+        assert_eq!(vec![4, 0], prev_boundaries("foo_bar", true));
+        assert_eq!(vec![3, 7], next_boundaries("foo_bar", true));
+    }
+
+    #[test]
+    fn camel_case() {
+        assert_eq!(vec![3, 0], prev_boundaries("fooBar", true));
+        assert_eq!(vec![3, 6], next_boundaries("fooBar", true));
+        assert_eq!(vec![4, 0], prev_boundaries("HTTPServer", true));
+        assert_eq!(vec![4, 10], next_boundaries("HTTPServer", true));
+    }
+
+    #[test]
+    fn screaming_case() {
+        assert_eq!(vec![4, 0], prev_boundaries("FOO_BAR", true));
+        assert_eq!(vec![3, 7], next_boundaries("FOO_BAR", true));
+    }
+
+    #[test]
+    fn word_counts() {
+        assert_eq!(0, count_words(""));
+        assert_eq!(0, count_words("  -- \n"));
+        assert_eq!(3, count_words("don't stop, me"));
+        assert_eq!(2, count_words("fa\u{e7}ade \u{43f}\u{440}\u{438}"));
+        assert_eq!(2, count_words("1984\u{3000}\u{65e5}\u{672c}"));
+    }
+}